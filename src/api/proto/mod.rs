@@ -1,14 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 pub use semver::{Version, VersionReq};
 
-pub type Features = HashMap<String, Vec<String>>;
+pub type Features = BTreeMap<String, Vec<String>>;
 
 #[derive(Debug, serde::Serialize)]
 pub struct CrateListItem {
     pub name: String,
     pub max_version: Version,
     pub description: String,
+    pub downloads: u64,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -22,6 +23,187 @@ pub struct SearchMeta {
     pub total: usize,
 }
 
+/// `GET /api/v1/crates/{name}`, shaped like the crates.io response.
+#[derive(Debug, serde::Serialize)]
+pub struct CrateResult {
+    #[serde(rename = "crate")]
+    pub krate: CrateInfo,
+    pub versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CrateInfo {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub repository: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub max_version: Version,
+    pub max_stable_version: Option<Version>,
+    pub newest_version: Version,
+    pub downloads: u64,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VersionsResult {
+    pub versions: Vec<VersionInfo>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VersionResult {
+    pub version: VersionInfo,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VersionInfo {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub num: Version,
+    pub dl_path: String,
+    pub yanked: bool,
+    pub description: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub repository: Option<String>,
+    pub license: Option<String>,
+    pub authors: Vec<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub features: Features,
+    pub links: Option<String>,
+    pub rust_version: Option<String>,
+    pub deps: Vec<CrateDep>,
+    pub downloads: u64,
+    pub updated_at: Option<String>,
+}
+
+/// `GET /api/v1/crates/{name}/reverse_dependencies`
+#[derive(Debug, serde::Serialize)]
+pub struct ReverseDependencies {
+    pub dependencies: Vec<ReverseDependency>,
+    pub meta: SearchMeta,
+}
+
+/// A version of another crate depending on the requested one.
+#[derive(Debug, serde::Serialize)]
+pub struct ReverseDependency {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub num: Version,
+    pub yanked: bool,
+    pub req: VersionReq,
+    pub kind: CrateDepKind,
+    pub optional: bool,
+    pub default_features: bool,
+    pub features: Vec<String>,
+    pub target: Option<String>,
+}
+
+/// `GET /api/v1/crates/{name}/{version}/resolve`
+#[derive(Debug, serde::Serialize)]
+pub struct Resolution {
+    pub packages: Vec<ResolvedPackage>,
+    pub issues: Vec<ResolveIssue>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ResolvedPackage {
+    pub name: String,
+    pub version: Version,
+    pub features: Vec<String>,
+}
+
+/// A problem found while resolving. `package` is the `name@version`
+/// declaring the dependency or feature.
+#[derive(Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ResolveIssue {
+    /// No version of the dependency in the index matches.
+    Unresolvable {
+        package: String,
+        dependency: String,
+        req: VersionReq,
+    },
+
+    /// Only yanked versions match, the newest of them was used.
+    Yanked {
+        package: String,
+        dependency: String,
+        version: Version,
+    },
+
+    /// The requirement excludes the semver compatible version of the
+    /// dependency selected for another package.
+    Conflict {
+        package: String,
+        dependency: String,
+        req: VersionReq,
+        selected: Version,
+    },
+
+    /// Several semver incompatible versions of a crate end up in the graph.
+    DuplicateMajor {
+        dependency: String,
+        versions: Vec<Version>,
+    },
+
+    /// The dependency comes from another registry, so it isn't followed.
+    OtherRegistry {
+        package: String,
+        dependency: String,
+        registry: String,
+    },
+
+    UnknownFeature {
+        package: String,
+        feature: String,
+    },
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CrateDownloads {
+    pub downloads: u64,
+    pub versions: Vec<VersionDownloads>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VersionDownloads {
+    pub num: Version,
+    pub downloads: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OkResult {
+    pub ok: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OwnersMsgResult {
+    pub ok: bool,
+    pub msg: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct OwnersResult {
+    pub users: Vec<Owner>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Owner {
+    pub id: u32,
+    pub login: String,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct OwnersRequest {
+    pub users: Vec<String>,
+}
+
 #[derive(Default, Debug, serde::Serialize)]
 pub struct PublishResult {
     pub warnings: PublishWarnings,
@@ -34,33 +216,97 @@ pub struct PublishWarnings {
     pub other: Vec<String>,
 }
 
+/// A line of a crate's index file.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct PublishedCrate {
     pub name: String,
     pub vers: Version,
-    pub deps: Vec<CrateDep>,
+    pub deps: Vec<IndexDep>,
     pub cksum: String,
     pub features: Features,
     pub yanked: bool,
     pub links: Option<String>,
+    #[serde(default)]
     pub v: u8,
+    #[serde(default)]
     pub features2: Features,
-    pub rust_version: Option<Version>,
+    pub rust_version: Option<String>,
 }
 
-#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CrateDepKind {
     Dev,
     Build,
+    #[default]
     Normal,
 }
 
+/// A dependency as listed in the index. Unlike in [`CrateDep`], `name` is
+/// the name used in `Cargo.toml`, and `package` the actual crate name when
+/// the dependency is renamed.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct IndexDep {
+    pub name: String,
+    pub req: VersionReq,
+    pub features: Vec<String>,
+    pub optional: bool,
+    pub default_features: bool,
+    pub target: Option<String>,
+    #[serde(default)]
+    pub kind: CrateDepKind,
+    pub registry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+}
+
+impl From<&CrateDep> for IndexDep {
+    fn from(dep: &CrateDep) -> Self {
+        let (name, package) = match &dep.explicit_name_in_toml {
+            Some(renamed) => (renamed.clone(), Some(dep.name.clone())),
+            None => (dep.name.clone(), None),
+        };
+
+        Self {
+            name,
+            req: dep.version_req.clone(),
+            features: dep.features.clone(),
+            optional: dep.optional,
+            default_features: dep.default_features,
+            target: dep.target.clone(),
+            kind: dep.kind,
+            registry: dep.registry.clone(),
+            package,
+        }
+    }
+}
+
+impl From<IndexDep> for CrateDep {
+    fn from(dep: IndexDep) -> Self {
+        let (name, explicit_name_in_toml) = match dep.package {
+            Some(package) => (package, Some(dep.name)),
+            None => (dep.name, None),
+        };
+
+        Self {
+            name,
+            version_req: dep.req,
+            features: dep.features,
+            optional: dep.optional,
+            default_features: dep.default_features,
+            target: dep.target,
+            kind: dep.kind,
+            registry: dep.registry,
+            explicit_name_in_toml,
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct CrateDep {
     pub name: String,
     pub version_req: VersionReq,
-    pub features: String,
+    pub features: Vec<String>,
     pub optional: bool,
     pub default_features: bool,
     pub target: Option<String>,
@@ -69,7 +315,7 @@ pub struct CrateDep {
     pub explicit_name_in_toml: Option<String>,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct CrateMeta {
     pub name: String,
     pub vers: Version,
@@ -84,10 +330,57 @@ pub struct CrateMeta {
     pub keywords: Vec<String>,
     pub categories: Vec<String>,
     pub license: Option<String>,
+    pub license_file: Option<String>,
     pub repository: Option<String>,
     pub badges: HashMap<String, HashMap<String, String>>,
     pub links: Option<String>,
-    pub rust_version: Option<Version>,
+    pub rust_version: Option<String>,
+}
+
+#[cfg(test)]
+impl CrateMeta {
+    /// A version without any dependencies or metadata, for tests to fill in
+    /// just the fields they are about.
+    pub fn for_test(name: &str, vers: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            vers: vers.parse().expect("version"),
+            deps: Vec::new(),
+            features: Features::new(),
+            authors: Vec::new(),
+            description: None,
+            documentation: None,
+            homepage: None,
+            readme: None,
+            readme_file: None,
+            keywords: Vec::new(),
+            categories: Vec::new(),
+            license: None,
+            license_file: None,
+            repository: None,
+            badges: HashMap::new(),
+            links: None,
+            rust_version: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl CrateDep {
+    /// A plain normal dependency from this registry.
+    pub fn for_test(name: &str, version_req: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version_req: version_req.parse().expect("version requirement"),
+            features: Vec::new(),
+            optional: false,
+            default_features: true,
+            target: None,
+            kind: CrateDepKind::Normal,
+            registry: None,
+            explicit_name_in_toml: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use axum::{Json, extract};
+
+use crate::{Error, IndexState, Result, api, auth::ReadAuth, downloads::Downloads};
+
+#[derive(serde::Deserialize)]
+pub struct Args {
+    name: String,
+}
+
+/// Lists the download counts of every version of a crate.
+pub async fn crate_downloads(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+) -> Result<Json<api::CrateDownloads>> {
+    let versions = mtx
+        .read()
+        .await
+        .get_crate(&args.name)
+        .ok_or(Error::NotFound)?
+        .into_iter()
+        .map(|entry| entry.meta.vers.clone())
+        .collect::<Vec<_>>();
+
+    let counts = downloads.versions(&args.name).await;
+
+    let versions = versions
+        .into_iter()
+        .rev()
+        .map(|num| api::VersionDownloads {
+            downloads: counts.get(&num).copied().unwrap_or_default(),
+            num,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Json(api::CrateDownloads {
+        downloads: versions.iter().map(|v| v.downloads).sum(),
+        versions,
+    }))
+}
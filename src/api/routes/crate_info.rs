@@ -0,0 +1,162 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{Json, extract};
+
+use crate::{
+    Error, IndexEntry, IndexState, Result, Settings, api, auth::ReadAuth, downloads::Downloads,
+    index,
+};
+
+#[derive(serde::Deserialize)]
+pub struct CrateArgs {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct VersionArgs {
+    name: String,
+    version: api::Version,
+}
+
+/// Clones the versions of a crate, newest first.
+async fn load_versions(IndexState(mtx): &IndexState, name: &str) -> Result<Vec<IndexEntry>> {
+    let mut versions = mtx
+        .read()
+        .await
+        .clone_versions_ignore_case(name)
+        .ok_or(Error::NotFound)?;
+    versions.reverse();
+
+    Ok(versions)
+}
+
+fn version_info(
+    entry: IndexEntry,
+    counts: &BTreeMap<api::Version, u64>,
+    settings: &Settings,
+) -> api::VersionInfo {
+    let IndexEntry {
+        meta,
+        yanked,
+        updated_at,
+        ..
+    } = entry;
+
+    api::VersionInfo {
+        dl_path: format!(
+            "{}/{}/{}/download",
+            settings.path_prefix, meta.name, meta.vers
+        ),
+        downloads: counts.get(&meta.vers).copied().unwrap_or_default(),
+        krate: meta.name,
+        num: meta.vers,
+        yanked,
+        description: meta.description,
+        homepage: meta.homepage,
+        documentation: meta.documentation,
+        repository: meta.repository,
+        license: meta.license,
+        authors: meta.authors,
+        keywords: meta.keywords,
+        categories: meta.categories,
+        features: meta.features,
+        links: meta.links,
+        rust_version: meta.rust_version,
+        deps: meta.deps,
+        updated_at: updated_at.map(index::rfc3339),
+    }
+}
+
+pub async fn get_crate_info(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<CrateArgs>,
+    extract::Extension(index): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Result<Json<api::CrateResult>> {
+    let versions = load_versions(&index, &args.name).await?;
+
+    // Versions are sorted, so the first one is the highest.
+    let newest = versions.first().ok_or(Error::NotFound)?;
+    let max_version = versions
+        .iter()
+        .find(|entry| !entry.yanked)
+        .unwrap_or(newest);
+    let max_stable_version = versions
+        .iter()
+        .find(|entry| !entry.yanked && entry.meta.vers.pre.is_empty());
+
+    let counts = downloads.versions(&newest.meta.name).await;
+
+    let krate = api::CrateInfo {
+        id: newest.meta.name.clone(),
+        name: newest.meta.name.clone(),
+        description: max_version.meta.description.clone(),
+        homepage: max_version.meta.homepage.clone(),
+        documentation: max_version.meta.documentation.clone(),
+        repository: max_version.meta.repository.clone(),
+        keywords: max_version.meta.keywords.clone(),
+        categories: max_version.meta.categories.clone(),
+        max_version: max_version.meta.vers.clone(),
+        max_stable_version: max_stable_version.map(|entry| entry.meta.vers.clone()),
+        newest_version: newest.meta.vers.clone(),
+        downloads: counts.values().sum(),
+        updated_at: versions
+            .iter()
+            .filter_map(|entry| entry.updated_at)
+            .max()
+            .map(index::rfc3339),
+    };
+
+    let versions = versions
+        .into_iter()
+        .map(|entry| version_info(entry, &counts, &settings))
+        .collect();
+
+    Ok(Json(api::CrateResult { krate, versions }))
+}
+
+pub async fn list_versions(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<CrateArgs>,
+    extract::Extension(index): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Result<Json<api::VersionsResult>> {
+    let versions = load_versions(&index, &args.name).await?;
+
+    let Some(first) = versions.first() else {
+        return Ok(Json(api::VersionsResult {
+            versions: Vec::new(),
+        }));
+    };
+
+    let counts = downloads.versions(&first.meta.name).await;
+
+    Ok(Json(api::VersionsResult {
+        versions: versions
+            .into_iter()
+            .map(|entry| version_info(entry, &counts, &settings))
+            .collect(),
+    }))
+}
+
+pub async fn get_version(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<VersionArgs>,
+    extract::Extension(index): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Result<Json<api::VersionResult>> {
+    let entry = load_versions(&index, &args.name)
+        .await?
+        .into_iter()
+        .find(|entry| entry.meta.vers == args.version)
+        .ok_or(Error::NotFound)?;
+
+    let counts = downloads.versions(&entry.meta.name).await;
+
+    Ok(Json(api::VersionResult {
+        version: version_info(entry, &counts, &settings),
+    }))
+}
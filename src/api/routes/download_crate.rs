@@ -1,10 +1,19 @@
+use std::sync::Arc;
+
 use axum::{
+    body::Body,
     extract,
-    http::{HeaderMap, HeaderName, HeaderValue},
+    http::{self, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
 };
-use bytes::Bytes;
 
-use crate::{Result, S3Storage, api::Version};
+use crate::{
+    Error, IndexState, Result, Settings, StoreState, Upstream,
+    api::Version,
+    auth::ReadAuth,
+    downloads::Downloads,
+    store::{self, ByteRange, ObjectStream},
+};
 
 #[derive(serde::Deserialize)]
 pub struct Args {
@@ -12,19 +21,131 @@ pub struct Args {
     version: Version,
 }
 
+/// Streams a `.crate` file, or the part of it asked for with `Range`.
+#[allow(clippy::too_many_arguments)]
 pub async fn download_crate(
+    _: ReadAuth,
+    headers: HeaderMap,
     extract::Path(args): extract::Path<Args>,
-    extract::Extension(store): extract::Extension<S3Storage>,
-) -> Result<(HeaderMap, Bytes)> {
-    let crate_file = store.download(&args.crate_name, &args.version).await?;
-
-    let len_str = format!("{}", crate_file.size);
-
-    Ok((
-        HeaderMap::from_iter([(
-            HeaderName::from_static("content-length"),
-            HeaderValue::from_str(&len_str).expect("valid header"),
-        )]),
-        crate_file.data,
-    ))
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(upstream): extract::Extension<Option<Arc<Upstream>>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+) -> Result<Response> {
+    let Args {
+        crate_name,
+        version,
+    } = args;
+
+    let range = headers
+        .get(http::header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(ByteRange::parse);
+
+    let index_read = mtx.read().await;
+    let is_local = index_read.crate_name_ignore_case(&crate_name).is_some();
+    let local_cksum = index_read
+        .get_crate_version(&crate_name, &version)
+        .map(|entry| entry.cksum.clone());
+    drop(index_read);
+
+    let (cksum, object) = match (local_cksum, upstream) {
+        (Some(cksum), _) => {
+            // Authorization is checked above, the URL only grants access to
+            // this one object for a short while.
+            if let Some(expires_in) = settings.presigned_downloads
+                && let Some(url) = store
+                    .presigned_url(&store::crate_path(&crate_name, &version), expires_in)
+                    .await?
+            {
+                downloads.count(&crate_name, &version);
+                return Ok(redirect(&url));
+            }
+
+            let object = store.download(&crate_name, &version, range).await?;
+
+            // Resumed downloads were counted when they started.
+            if object.range.is_none_or(|(first, _)| first == 0) {
+                downloads.count(&crate_name, &version);
+            }
+
+            (cksum, object)
+        }
+
+        (None, Some(upstream)) if !is_local && crate_name.is_ascii() => {
+            let cksum = upstream
+                .cksum(store.as_ref(), &crate_name, &version)
+                .await?;
+            let object = upstream
+                .download(store.as_ref(), &crate_name, &version, range)
+                .await?;
+
+            (cksum, object)
+        }
+
+        _ => return Err(Error::NotFound),
+    };
+
+    let ObjectStream { body, size, range } = object;
+
+    // Published versions never change, and the checksum identifies them.
+    let cache_control = if settings.auth_required {
+        "private, max-age=31536000, immutable"
+    } else {
+        "public, max-age=31536000, immutable"
+    };
+
+    let mut res_headers = HeaderMap::from_iter([
+        (
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/gzip"),
+        ),
+        (
+            http::header::ETAG,
+            HeaderValue::from_str(&format!("\"{cksum}\"")).expect("valid header"),
+        ),
+        (
+            http::header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        ),
+        (
+            http::header::ACCEPT_RANGES,
+            HeaderValue::from_static("bytes"),
+        ),
+    ]);
+
+    let (status, len) = match range {
+        Some((first, last)) => {
+            res_headers.insert(
+                http::header::CONTENT_RANGE,
+                HeaderValue::from_str(&format!("bytes {first}-{last}/{size}"))
+                    .expect("valid header"),
+            );
+
+            (StatusCode::PARTIAL_CONTENT, last - first + 1)
+        }
+
+        None => (StatusCode::OK, size),
+    };
+
+    res_headers.insert(http::header::CONTENT_LENGTH, HeaderValue::from(len));
+
+    Ok((status, res_headers, Body::from_stream(body)).into_response())
+}
+
+fn redirect(url: &str) -> Response {
+    let headers = HeaderMap::from_iter([
+        (
+            http::header::LOCATION,
+            HeaderValue::from_str(url).expect("valid header"),
+        ),
+        // The URL expires, so it must not outlive it in any cache.
+        (
+            http::header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ),
+    ]);
+
+    (StatusCode::FOUND, headers).into_response()
 }
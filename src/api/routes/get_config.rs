@@ -1,14 +1,30 @@
-use axum::Json;
+use std::sync::Arc;
+
+use axum::{Json, extract};
+
+use crate::{Settings, auth::ReadAuth};
 
 #[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Config {
-    dl: String,
-    api: String,
+    pub dl: String,
+    pub api: String,
+    pub auth_required: bool,
+}
+
+impl From<&Settings> for Config {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            dl: settings.dl_template.clone(),
+            api: settings.public_url.clone(),
+            auth_required: settings.auth_required,
+        }
+    }
 }
 
-pub async fn get_config() -> Json<Config> {
-    Json(Config {
-        dl: "http://localhost:3000/{crate}/{version}/download".into(),
-        api: "http://localhost:3000".into(),
-    })
+pub async fn get_config(
+    _: ReadAuth,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Json<Config> {
+    Json(Config::from(settings.as_ref()))
 }
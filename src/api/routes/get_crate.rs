@@ -1,56 +1,171 @@
 use axum::{
     extract,
-    http::{self, HeaderMap},
+    http::{self, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
 };
 
-use crate::{Error, IndexEntry, IndexState, NdJson, Result, api};
-
-#[derive(serde::Deserialize, Debug)]
-pub struct Args {
-    #[allow(dead_code)]
-    s1: String,
-
-    #[allow(dead_code)]
-    s2: String,
-    name: String,
-}
+use crate::{Error, IndexState, NdJson, Result, StoreState, Upstream, auth::ReadAuth, index};
 
+/// Serves the sparse index files at `/1/{name}`, `/2/{name}`,
+/// `/3/{c}/{name}` and `/{s1}/{s2}/{name}`. Crates that aren't published
+/// here come from the upstream registry, if one is configured.
 pub async fn get_crate(
-    extract::Path(args): extract::Path<Args>,
+    _: ReadAuth,
+    headers: HeaderMap,
+    uri: http::Uri,
     extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
-) -> Result<(HeaderMap, NdJson<api::PublishedCrate>)> {
-    let read_index = mtx.read().await;
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(upstream): extract::Extension<Option<Arc<Upstream>>>,
+) -> Result<Response> {
+    let path = uri.path().trim_start_matches('/');
+    let name = path.rsplit('/').next().ok_or(Error::NotFound)?;
 
-    let Some(versions_iter) = read_index.get_crate(&args.name) else {
+    // Cargo always asks for the lowercased name in the matching shard.
+    if !name.is_ascii() || path != index::index_path(name) {
         return Err(Error::NotFound);
+    }
+
+    let read_index = mtx.read().await;
+
+    let Some(versions_iter) = read_index
+        .crate_name_ignore_case(name)
+        .and_then(|name| read_index.get_crate(name))
+    else {
+        drop(read_index);
+
+        let upstream = upstream.ok_or(Error::NotFound)?;
+        let body = upstream.index_file(store.as_ref(), name).await?;
+
+        return Ok(index_response(&headers, body, None));
     };
 
+    let mut last_modified = None;
+
     let versions_vec = versions_iter
         .into_iter()
-        .map(
-            |IndexEntry {
-                 cksum,
-                 meta,
-                 yanked,
-             }| api::PublishedCrate {
-                name: meta.name.clone(),
-                vers: meta.vers.clone(),
-                deps: meta.deps.clone(),
-                cksum: cksum.into(),
-                features: meta.features.clone(),
-                yanked: *yanked,
-                links: meta.links.clone(),
-                v: 2,
-                features2: meta.features.clone(),
-                rust_version: meta.rust_version.clone(),
-            },
-        )
+        .map(|entry| {
+            last_modified = last_modified.max(entry.updated_at);
+            entry.index_line()
+        })
         .collect::<Vec<_>>();
 
-    let headers = HeaderMap::from_iter([(
-        http::HeaderName::from_static("content-type"),
-        http::HeaderValue::from_static("applicant/json"),
-    )]);
+    drop(read_index);
+
+    let body = Bytes::from(NdJson(versions_vec));
+    let last_modified = last_modified.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+    Ok(index_response(&headers, body, last_modified))
+}
+
+fn index_response(
+    headers: &HeaderMap,
+    body: Bytes,
+    last_modified: Option<std::time::SystemTime>,
+) -> Response {
+    let etag = format!("\"{}\"", sha256::digest(body.as_ref()));
+
+    let mut res_headers = HeaderMap::from_iter([
+        (
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        ),
+        (
+            http::header::ETAG,
+            HeaderValue::from_str(&etag).expect("valid header"),
+        ),
+    ]);
+
+    if let Some(last_modified) = last_modified {
+        res_headers.insert(
+            http::header::LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).expect("valid header"),
+        );
+    }
+
+    if is_not_modified(headers, &etag, last_modified) {
+        return (StatusCode::NOT_MODIFIED, res_headers).into_response();
+    }
+
+    (res_headers, body).into_response()
+}
+
+/// Evaluates the conditional request headers cargo sends on index updates.
+/// `If-None-Match` takes precedence over `If-Modified-Since`.
+fn is_not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<std::time::SystemTime>,
+) -> bool {
+    if let Some(if_none_match) = headers.get(http::header::IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        });
+    }
+
+    let if_modified_since = headers
+        .get(http::header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok());
+
+    match (if_modified_since, last_modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: http::HeaderName, value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(name, HeaderValue::from_str(value).unwrap())])
+    }
+
+    #[test]
+    fn if_none_match() {
+        let etag = "\"abc\"";
+        let check =
+            |value| is_not_modified(&headers(http::header::IF_NONE_MATCH, value), etag, None);
+
+        assert!(check("\"abc\""));
+        assert!(check("W/\"abc\""));
+        assert!(check("\"old\", \"abc\""));
+        assert!(check("*"));
+        assert!(!check("\"old\""));
+        assert!(!check("abc"));
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let at = |secs| httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+        let check = |value: &str, modified| {
+            is_not_modified(
+                &headers(http::header::IF_MODIFIED_SINCE, value),
+                "\"abc\"",
+                modified,
+            )
+        };
+
+        assert!(check(&at(1_700_000_000), Some(modified)));
+        assert!(!check(&at(1_699_999_999), Some(modified)));
+        assert!(!check(&at(1_700_000_000), None));
+        assert!(!check("yesterday", Some(modified)));
 
-    Ok((headers, NdJson(versions_vec)))
+        // `If-None-Match` wins, even when the date alone would match.
+        let mut both = headers(http::header::IF_MODIFIED_SINCE, &at(1_700_000_000));
+        both.insert(
+            http::header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"old\""),
+        );
+        assert!(!is_not_modified(&both, "\"abc\"", Some(modified)));
+    }
 }
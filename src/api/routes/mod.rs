@@ -1,10 +1,27 @@
+mod crate_downloads;
+mod crate_info;
 mod download_crate;
 mod get_config;
 mod get_crate;
+mod owners;
 mod publish_crate;
+mod readme;
+mod resolve;
+mod reverse_dependencies;
 mod search_crates;
+mod yank_crate;
 
 pub use {
-    download_crate::download_crate, get_config::get_config, get_crate::get_crate,
-    publish_crate::publish_crate, search_crates::search_crates,
+    crate_downloads::crate_downloads,
+    crate_info::{get_crate_info, get_version, list_versions},
+    download_crate::download_crate,
+    get_config::{Config, get_config},
+    get_crate::get_crate,
+    owners::{add_owners, list_owners, remove_owners},
+    publish_crate::publish_crate,
+    readme::get_readme,
+    resolve::resolve_version,
+    reverse_dependencies::reverse_dependencies,
+    search_crates::search_crates,
+    yank_crate::{unyank_crate, yank_crate},
 };
@@ -0,0 +1,114 @@
+use axum::{Json, extract};
+
+use crate::{
+    Error, IndexState, Result, StoreState, api,
+    auth::{Auth, ReadAuth, Scope},
+    error::Optional,
+    owners,
+};
+
+#[derive(serde::Deserialize)]
+pub struct Args {
+    name: String,
+}
+
+pub async fn list_owners(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+) -> Result<Json<api::OwnersResult>> {
+    if mtx.read().await.get_crate(&args.name).is_none() {
+        return Err(Error::NotFound);
+    }
+
+    let owners = store
+        .load_owners(&args.name)
+        .await
+        .optional()?
+        .unwrap_or_default();
+
+    let users = owners
+        .users
+        .into_iter()
+        .zip(1..)
+        .map(|(login, id)| api::Owner {
+            id,
+            login,
+            name: None,
+        })
+        .collect();
+
+    Ok(Json(api::OwnersResult { users }))
+}
+
+pub async fn add_owners(
+    Auth(token): Auth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    Json(req): Json<api::OwnersRequest>,
+) -> Result<Json<api::OwnersMsgResult>> {
+    token.require(Scope::ChangeOwners, &args.name)?;
+
+    if mtx.read().await.get_crate(&args.name).is_none() {
+        return Err(Error::NotFound);
+    }
+
+    owners::require_owner(store.as_ref(), &token, &args.name).await?;
+
+    owners::update(store.as_ref(), &args.name, |owners| {
+        for login in &req.users {
+            if !owners.contains(login) {
+                owners.users.push(login.clone());
+            }
+        }
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(Json(api::OwnersMsgResult {
+        ok: true,
+        msg: format!("owners {} added to `{}`", req.users.join(", "), args.name),
+    }))
+}
+
+pub async fn remove_owners(
+    Auth(token): Auth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    Json(req): Json<api::OwnersRequest>,
+) -> Result<Json<api::OwnersMsgResult>> {
+    token.require(Scope::ChangeOwners, &args.name)?;
+
+    if mtx.read().await.get_crate(&args.name).is_none() {
+        return Err(Error::NotFound);
+    }
+
+    owners::require_owner(store.as_ref(), &token, &args.name).await?;
+
+    owners::update(store.as_ref(), &args.name, |owners| {
+        owners.users.retain(|login| !req.users.contains(login));
+
+        if owners.users.is_empty() {
+            return Err(Error::BadRequest(format!(
+                "cannot remove all owners of `{}`",
+                args.name
+            )));
+        }
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(Json(api::OwnersMsgResult {
+        ok: true,
+        msg: format!(
+            "owners {} removed from `{}`",
+            req.users.join(", "),
+            args.name
+        ),
+    }))
+}
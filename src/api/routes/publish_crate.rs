@@ -1,58 +1,200 @@
-use axum::{Json, extract, http::HeaderMap};
+use std::sync::Arc;
+
+use axum::{Json, extract};
 
 use bytes::{Buf, Bytes};
+use tokio::sync::RwLock;
+
+use crate::{
+    Error, Index, IndexState, Result, Settings, StoreState, Upstream, api,
+    auth::{Auth, Scope},
+    changelog,
+    owners::{self, Owners},
+    store::Store,
+    tarball, validate,
+};
+
+/// Reads one `u32` length prefixed chunk of the publish body.
+fn split_chunk(bs: &mut Bytes, what: &str) -> Result<Bytes> {
+    if bs.remaining() < 4 {
+        return Err(Error::BadRequest(format!(
+            "publish body truncated before the {what} length"
+        )));
+    }
+
+    let len = bs.get_u32_le() as usize;
+
+    if bs.remaining() < len {
+        return Err(Error::BadRequest(format!(
+            "publish body truncated, the {what} is {} bytes but {len} were announced",
+            bs.remaining()
+        )));
+    }
 
-use crate::{IndexState, Result, S3Storage, api};
+    Ok(bs.split_to(len))
+}
 
 pub async fn publish_crate(
-    headers: HeaderMap,
-    extract::Extension(store): extract::Extension<S3Storage>,
+    Auth(token): Auth,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
     extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+    extract::Extension(upstream): extract::Extension<Option<Arc<Upstream>>>,
     mut bs: Bytes,
 ) -> Result<Json<api::PublishResult>> {
-    println!("Publish: {headers:#?}");
-    let total_len = bs.len();
-    let json_len = bs.get_u32_le() as usize;
-    let json_data = bs.split_to(json_len);
-
-    let meta = match serde_json::from_slice::<api::CrateMeta>(&json_data) {
-        Ok(meta) => meta,
-
-        Err(err) => {
-            eprintln!("deserializing CrateMeta: {err}");
-            eprintln!(
-                "raw: {}",
-                str::from_utf8(&json_data).expect("json must be utf8")
-            );
-            panic!("shit");
-        }
-    };
+    let json_data = split_chunk(&mut bs, "metadata")?;
+    let data = split_chunk(&mut bs, "crate file")?;
+
+    if bs.has_remaining() {
+        return Err(Error::BadRequest(format!(
+            "{} unexpected trailing bytes in publish body",
+            bs.remaining()
+        )));
+    }
+
+    let meta = serde_json::from_slice::<api::CrateMeta>(&json_data)
+        .map_err(|err| Error::BadRequest(format!("invalid crate metadata: {err}")))?;
+
+    let mut warnings = validate::validate_meta(&meta, &settings)?;
+    settings.policy.apply(&meta, &mut warnings)?;
 
     // check if the crate exists
-    {
+    let is_new_crate = {
         let idx_read = mtx.read().await;
         if idx_read.get_crate_version(&meta.name, &meta.vers).is_some() {
-            return Err(crate::error::Error::CrateExists {
+            return Err(Error::CrateExists {
                 name: meta.name,
                 version: meta.vers,
             });
         }
+
+        if let Some(existing) = idx_read.similar_crate_name(&meta.name) {
+            return Err(Error::BadRequest(format!(
+                "crate `{}` conflicts with the existing crate `{existing}`",
+                meta.name
+            )));
+        }
+
+        if let Some(existing) = idx_read
+            .get_crate(&meta.name)
+            .into_iter()
+            .flatten()
+            .find(|entry| validate::differs_only_in_build(&entry.meta.vers, &meta.vers))
+        {
+            return Err(Error::BadRequest(format!(
+                "version `{}` only differs from the published `{}` in its build metadata",
+                meta.vers, existing.meta.vers
+            )));
+        }
+
+        idx_read.get_crate(&meta.name).is_none()
+    };
+
+    if is_new_crate {
+        token.require(Scope::PublishNew, &meta.name)?;
+
+        // A crate published here replaces the upstream one of that name for
+        // every client, so only take over upstream names deliberately.
+        if let Some(upstream) = &upstream
+            && !settings.allow_shadowing_upstream
+        {
+            match upstream.fetch_index_file(&meta.name).await {
+                Ok(_) => {
+                    return Err(Error::BadRequest(format!(
+                        "crate `{}` exists in the upstream registry and would be shadowed",
+                        meta.name
+                    )));
+                }
+
+                Err(Error::NotFound) => (),
+                Err(err) => return Err(err),
+            }
+        }
+    } else {
+        token.require(Scope::PublishUpdate, &meta.name)?;
+        owners::require_owner(store.as_ref(), &token, &meta.name).await?;
     }
 
-    let data_len = bs.get_u32_le() as usize;
-    let data = bs.split_to(data_len);
+    let (meta, readme) = {
+        let data = data.clone();
+        let limits = settings.crate_limits;
+
+        tokio::task::spawn_blocking(move || {
+            let readme = tarball::verify(&meta, &data, limits)?;
+
+            Result::Ok((meta, readme))
+        })
+        .await
+        .map_err(std::io::Error::other)??
+    };
+
+    // Claim a new crate before uploading anything, so that concurrent first
+    // publishes by different users can't both end up as its owner.
+    let created_owners = if is_new_crate {
+        let owners = Owners {
+            users: vec![token.name.clone()],
+        };
+
+        match store.create_owners(&meta.name, &owners).await {
+            Ok(()) => true,
 
-    assert_eq!(
-        total_len,
-        4 + json_len + 4 + data_len,
-        "the total size of the data"
-    );
+            Err(Error::Conflict) => {
+                owners::require_owner(store.as_ref(), &token, &meta.name).await?;
+                false
+            }
 
+            Err(err) => return Err(err),
+        }
+    } else {
+        false
+    };
+
+    let crate_name = meta.name.clone();
+    let version = meta.vers.clone();
+    let res = store_and_commit(store.as_ref(), &mtx, meta, data).await;
+
+    if res.is_err()
+        && created_owners
+        && let Err(err) = store.delete_owners(&crate_name).await
+    {
+        eprintln!("removing owners of `{crate_name}` after failed publish: {err}");
+    }
+
+    res?;
+
+    // The version is published already, so this is only worth a log line.
+    if let Some(readme) = readme
+        && let Err(err) = store.store_readme(&crate_name, &version, readme).await
+    {
+        eprintln!("storing README of `{crate_name}-{version}`: {err}");
+    }
+
+    Ok(Json(api::PublishResult { warnings }))
+}
+
+/// Stores the crate and records it in the change log. If it can't be
+/// recorded, the stored objects are removed again so that no half published
+/// version is left behind.
+async fn store_and_commit(
+    store: &dyn Store,
+    mtx: &RwLock<Index>,
+    meta: api::CrateMeta,
+    data: Bytes,
+) -> Result<()> {
     let index_entry = store.store_crate(meta, data).await?;
 
+    let name = index_entry.meta.name.clone();
+    let version = index_entry.meta.vers.clone();
+
     let mut index_write = mtx.write().await;
 
-    index_write.add_crate_meta(index_entry);
+    if let Err(err) = changelog::commit(store, &mut index_write, index_entry).await {
+        if let Err(err) = store.remove_crate(&name, &version).await {
+            eprintln!("removing `{name}-{version}` after failed publish: {err}");
+        }
+
+        return Err(err);
+    }
 
-    Ok(Json(api::PublishResult::default()))
+    Ok(())
 }
@@ -0,0 +1,72 @@
+use axum::{
+    extract,
+    http::{self, HeaderValue},
+    response::{IntoResponse, Response},
+};
+
+use crate::{Error, IndexState, Result, StoreState, api, auth::ReadAuth, readme};
+
+#[derive(serde::Deserialize)]
+pub struct Args {
+    name: String,
+    version: api::Version,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    /// The README as it is in the crate.
+    #[default]
+    Raw,
+
+    /// Rendered from Markdown to sanitized HTML.
+    Html,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Query {
+    #[serde(default)]
+    format: Format,
+}
+
+/// Serves the README of a version, as extracted from its `.crate` file on
+/// publish.
+pub async fn get_readme(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Query(query): extract::Query<Query>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+) -> Result<Response> {
+    let readme_file = mtx
+        .read()
+        .await
+        .get_crate_version(&args.name, &args.version)
+        .ok_or(Error::NotFound)?
+        .meta
+        .readme_file
+        .clone()
+        .ok_or(Error::NotFound)?;
+
+    let readme = store.load_readme(&args.name, &args.version).await?;
+
+    let (content_type, body) = match query.format {
+        Format::Raw if readme::is_markdown(&readme_file) => {
+            ("text/markdown; charset=utf-8", readme)
+        }
+        Format::Raw => ("text/plain; charset=utf-8", readme),
+        Format::Html => (
+            "text/html; charset=utf-8",
+            readme::render_html(&readme, &readme_file),
+        ),
+    };
+
+    Ok((
+        [(
+            http::header::CONTENT_TYPE,
+            HeaderValue::from_static(content_type),
+        )],
+        body,
+    )
+        .into_response())
+}
@@ -0,0 +1,47 @@
+use axum::{Json, extract};
+
+use crate::{IndexState, Result, api, auth::ReadAuth, resolve};
+
+#[derive(serde::Deserialize)]
+pub struct Args {
+    name: String,
+    version: api::Version,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Query {
+    /// Comma separated, like cargo's `--features`.
+    #[serde(default)]
+    features: String,
+
+    #[serde(default)]
+    no_default_features: bool,
+}
+
+/// Resolves the dependencies of a version within this registry and reports
+/// the problems found.
+pub async fn resolve_version(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Query(query): extract::Query<Query>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+) -> Result<Json<api::Resolution>> {
+    let features = query
+        .features
+        .split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let res = resolve::resolve(
+        &*mtx.read().await,
+        &args.name,
+        &args.version,
+        &features,
+        !query.no_default_features,
+    )?;
+
+    Ok(Json(res))
+}
@@ -0,0 +1,58 @@
+use axum::{Json, extract};
+
+use crate::{Error, IndexState, Result, api, auth::ReadAuth};
+
+#[derive(serde::Deserialize)]
+pub struct Path {
+    name: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct Args {
+    /// Only look at the newest version of every dependent crate.
+    #[serde(default)]
+    latest_only: bool,
+
+    #[serde(default)]
+    exclude_yanked: bool,
+}
+
+/// Lists the versions of crates in this registry depending on a crate,
+/// sorted by crate name and newest version first.
+pub async fn reverse_dependencies(
+    _: ReadAuth,
+    extract::Path(path): extract::Path<Path>,
+    extract::Query(args): extract::Query<Args>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+) -> Result<Json<api::ReverseDependencies>> {
+    let index_read = mtx.read().await;
+
+    let name = index_read
+        .crate_name_ignore_case(&path.name)
+        .ok_or(Error::NotFound)?;
+
+    let mut dependencies = index_read
+        .reverse_dependencies(name, args.latest_only, args.exclude_yanked)
+        .map(|(entry, dep)| api::ReverseDependency {
+            krate: entry.meta.name.clone(),
+            num: entry.meta.vers.clone(),
+            yanked: entry.yanked,
+            req: dep.version_req.clone(),
+            kind: dep.kind,
+            optional: dep.optional,
+            default_features: dep.default_features,
+            features: dep.features.clone(),
+            target: dep.target.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    dependencies.sort_by(|a, b| a.krate.cmp(&b.krate).then_with(|| b.num.cmp(&a.num)));
+
+    Ok(Json(api::ReverseDependencies {
+        meta: api::SearchMeta {
+            total: dependencies.len(),
+        },
+        dependencies,
+    }))
+}
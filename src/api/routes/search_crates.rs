@@ -1,21 +1,51 @@
+use std::sync::Arc;
+
 use axum::{Json, extract};
 
-use crate::{IndexState, api};
+use crate::{IndexState, api, auth::ReadAuth, downloads::Downloads, search::Sort};
+
+/// Upper limit for `per_page`, as on crates.io.
+const MAX_PER_PAGE: usize = 100;
 
 #[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct Args {
+    #[serde(default)]
     q: String,
+
+    #[serde(default = "default_page")]
+    page: usize,
+
+    #[serde(default = "default_per_page")]
     per_page: usize,
+
+    #[serde(default)]
+    sort: Sort,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    10
 }
 
 pub async fn search_crates(
+    _: ReadAuth,
     extract::Query(args): extract::Query<Args>,
     extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
 ) -> Json<api::SearchResult> {
-    let idx_read = mtx.read().await;
+    let totals = downloads.totals().await;
 
-    let res = idx_read.search_crates(&args.q, args.per_page);
+    let res = mtx.read().await.search_crates(
+        &args.q,
+        args.sort,
+        &totals,
+        args.page.max(1),
+        args.per_page.min(MAX_PER_PAGE),
+    );
 
     Json(res)
 }
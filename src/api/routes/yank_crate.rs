@@ -0,0 +1,76 @@
+use axum::{Json, extract};
+
+use crate::{
+    Error, IndexEntry, IndexState, Result, StoreState, api,
+    auth::{Auth, Scope},
+    changelog, index, owners,
+};
+
+#[derive(serde::Deserialize)]
+pub struct Args {
+    name: String,
+    version: api::Version,
+}
+
+pub async fn yank_crate(
+    Auth(token): Auth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(store): extract::Extension<StoreState>,
+    extract::Extension(index): extract::Extension<IndexState>,
+) -> Result<Json<api::OkResult>> {
+    token.require(Scope::Yank, &args.name)?;
+    owners::require_owner(store.0.as_ref(), &token, &args.name).await?;
+
+    set_yanked(args, store, index, true).await
+}
+
+pub async fn unyank_crate(
+    Auth(token): Auth,
+    extract::Path(args): extract::Path<Args>,
+    extract::Extension(store): extract::Extension<StoreState>,
+    extract::Extension(index): extract::Extension<IndexState>,
+) -> Result<Json<api::OkResult>> {
+    token.require(Scope::Yank, &args.name)?;
+    owners::require_owner(store.0.as_ref(), &token, &args.name).await?;
+
+    set_yanked(args, store, index, false).await
+}
+
+async fn set_yanked(
+    args: Args,
+    StoreState(store): StoreState,
+    IndexState(mtx): IndexState,
+    yanked: bool,
+) -> Result<Json<api::OkResult>> {
+    // Hold the write lock while storing so concurrent (un)yanks of the same
+    // version can't leave the store and the index disagreeing.
+    let mut index_write = mtx.write().await;
+
+    let entry = index_write
+        .get_crate_version(&args.name, &args.version)
+        .ok_or(Error::NotFound)?;
+
+    if entry.yanked != yanked {
+        let old = entry.clone();
+        let entry = IndexEntry {
+            yanked,
+            updated_at: Some(index::unix_now()),
+            ..old.clone()
+        };
+
+        store.update_crate_meta(&entry).await?;
+
+        if let Err(err) = changelog::commit(store.as_ref(), &mut index_write, entry).await {
+            if let Err(err) = store.update_crate_meta(&old).await {
+                eprintln!(
+                    "restoring `{}-{}` after failed (un)yank: {err}",
+                    args.name, args.version
+                );
+            }
+
+            return Err(err);
+        }
+    }
+
+    Ok(Json(api::OkResult { ok: true }))
+}
@@ -0,0 +1,170 @@
+//! API tokens for the write operations.
+//!
+//! Tokens are handed out once by the `create-token` command. Only their
+//! sha256 hash is kept in the store, next to the scopes they grant.
+
+use std::{fmt, sync::Arc};
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use base64::Engine;
+
+use crate::{Error, Result, Settings, StoreState, error::Optional};
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    PublishNew,
+    PublishUpdate,
+    Yank,
+    ChangeOwners,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PublishNew => "publish-new",
+            Self::PublishUpdate => "publish-update",
+            Self::Yank => "yank",
+            Self::ChangeOwners => "change-owners",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Token {
+    /// The user the token belongs to, which is also the login crate
+    /// ownership is recorded under.
+    pub name: String,
+    pub scopes: Vec<Scope>,
+
+    /// Crate names the token is limited to. A trailing `*` matches any
+    /// suffix, and an empty list allows every crate.
+    #[serde(default)]
+    pub crates: Vec<String>,
+}
+
+impl Token {
+    /// Creates a new random token value to hand out to the user.
+    pub fn generate() -> String {
+        let mut buf = [0u8; 32];
+        getrandom::fill(&mut buf).expect("generating random token");
+
+        format!(
+            "lgt_{}",
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(buf)
+        )
+    }
+
+    pub fn hash(token: &str) -> String {
+        sha256::digest(token)
+    }
+
+    pub fn allows(&self, scope: Scope, crate_name: &str) -> bool {
+        let crate_allowed = self.crates.is_empty()
+            || self
+                .crates
+                .iter()
+                .any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => crate_name.starts_with(prefix),
+                    None => crate_name == pattern,
+                });
+
+        crate_allowed && self.scopes.contains(&scope)
+    }
+
+    pub fn require(&self, scope: Scope, crate_name: &str) -> Result<()> {
+        if self.allows(scope, crate_name) {
+            Ok(())
+        } else {
+            Err(Error::Forbidden(format!(
+                "token `{}` does not have the `{scope}` scope for crate `{crate_name}`",
+                self.name
+            )))
+        }
+    }
+}
+
+/// Extracts the token sent by cargo in the `Authorization` header.
+pub struct Auth(pub Token);
+
+impl<S> FromRequestParts<S> for Auth
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self> {
+        let StoreState(store) = parts
+            .extensions
+            .get::<StoreState>()
+            .cloned()
+            .expect("StoreState extension");
+
+        let token = parts
+            .headers
+            .get(axum::http::header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or(Error::Unauthorized)?;
+
+        let token = token.strip_prefix("Bearer ").unwrap_or(token);
+
+        store
+            .load_token(&Token::hash(token))
+            .await
+            .optional()?
+            .map(Auth)
+            .ok_or(Error::Unauthorized)
+    }
+}
+
+/// Guards the read only routes. Any valid token passes, and no token is
+/// needed at all unless the registry is `auth-required`.
+pub struct ReadAuth;
+
+impl<S> FromRequestParts<S> for ReadAuth
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self> {
+        let settings = parts
+            .extensions
+            .get::<Arc<Settings>>()
+            .expect("Settings extension");
+
+        if settings.auth_required {
+            Auth::from_request_parts(parts, state).await?;
+        }
+
+        Ok(ReadAuth)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_crate_patterns() {
+        let token = Token {
+            name: "ci".into(),
+            scopes: vec![Scope::PublishUpdate],
+            crates: vec!["internal-*".into(), "exact".into()],
+        };
+
+        assert!(token.allows(Scope::PublishUpdate, "internal-utils"));
+        assert!(token.allows(Scope::PublishUpdate, "exact"));
+        assert!(!token.allows(Scope::PublishUpdate, "exactly"));
+        assert!(!token.allows(Scope::PublishNew, "internal-utils"));
+        assert!(!token.allows(Scope::PublishUpdate, "other"));
+    }
+}
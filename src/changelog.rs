@@ -0,0 +1,234 @@
+//! Keeps the in-memory indexes of several lagret replicas sharing a store in
+//! sync.
+//!
+//! Every publish and (un)yank is recorded as a numbered change object, which
+//! is written with [`Store::put_object_if_absent`] so that two replicas can
+//! never claim the same generation. Replicas polling the store look for the
+//! change after their own generation. A small generation marker tells them
+//! when changes they are missing were removed already.
+
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+
+use crate::{
+    Error, IndexState, Result,
+    error::Optional,
+    index::{Index, IndexEntry},
+    store::Store,
+};
+
+static CHANGES_DIR: &str = "changes";
+static GENERATION_PATH: &str = "index/generation";
+
+/// How many change objects are kept for replicas that fall behind. Older
+/// ones are removed, and replicas missing them reload the snapshot instead.
+const KEPT_CHANGES: u64 = 1000;
+
+fn change_path(generation: u64) -> String {
+    format!("{CHANGES_DIR}/{generation:020}.json")
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Change {
+    generation: u64,
+
+    /// The complete state of the changed version.
+    entry: IndexEntry,
+}
+
+async fn load_marker(store: &dyn Store) -> Result<u64> {
+    let Some(data) = store.get_object(GENERATION_PATH).await.optional()? else {
+        return Ok(0);
+    };
+
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Applies all changes made by other replicas since `index` was loaded.
+pub async fn catch_up(store: &dyn Store, index: &mut Index) -> Result<()> {
+    let marker = load_marker(store).await?;
+
+    loop {
+        let generation = index.generation + 1;
+
+        match store.get_object(&change_path(generation)).await {
+            Ok(data) => {
+                let change = serde_json::from_slice::<Change>(&data)?;
+
+                println!(
+                    "applying change {generation}: {} {}",
+                    change.entry.meta.name, change.entry.meta.vers
+                );
+                index.add_crate_meta(change.entry);
+                index.generation = change.generation;
+            }
+
+            // The change was removed already, skip ahead with the snapshot.
+            Err(Error::NotFound) if generation <= marker => {
+                let snapshot = store.load_index().await?;
+
+                if snapshot.generation >= generation {
+                    *index = snapshot;
+                } else {
+                    println!("change log has a gap at {generation}, rebuilding index");
+
+                    *index = store.rebuild_index().await?;
+                    index.generation = marker;
+                    return Ok(());
+                }
+            }
+
+            Err(Error::NotFound) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Records a new or changed version in the store, and applies it to `index`.
+/// Once the change object is written the change is durable, so an error
+/// means that nothing was recorded.
+pub async fn commit(store: &dyn Store, index: &mut Index, entry: IndexEntry) -> Result<()> {
+    let mut change = Change {
+        generation: index.generation + 1,
+        entry,
+    };
+
+    // Another replica got there first, apply its changes and take the next
+    // generation.
+    loop {
+        let data = Bytes::from(serde_json::to_vec(&change)?);
+
+        match store
+            .put_object_if_absent(&change_path(change.generation), data)
+            .await
+        {
+            Ok(()) => break,
+
+            Err(Error::Conflict) => {
+                catch_up(store, index).await?;
+                change.generation = index.generation + 1;
+            }
+
+            Err(err) => return Err(err),
+        }
+    }
+
+    let generation = change.generation;
+
+    index.add_crate_meta(change.entry);
+    index.generation = generation;
+
+    // Replicas and restarts find the change by probing the log even when
+    // these fail. The marker isn't written atomically with the change and can
+    // fall behind or even move backwards, which only delays the snapshot
+    // fallback of replicas missing removed changes until the next commit.
+    let marker = Bytes::from(serde_json::to_vec(&generation)?);
+    if let Err(err) = store.put_object(GENERATION_PATH, marker).await {
+        eprintln!("storing index generation {generation}: {err}");
+    }
+
+    if let Err(err) = store.store_index_snapshot(index).await {
+        eprintln!("storing index snapshot {generation}: {err}");
+    }
+
+    if generation > KEPT_CHANGES
+        && let Err(err) = store
+            .delete_object(&change_path(generation - KEPT_CHANGES))
+            .await
+    {
+        eprintln!("removing old change: {err}");
+    }
+
+    Ok(())
+}
+
+/// Applies new changes from other replicas to the index, if there are any.
+/// Only takes the write lock when the next change or a newer marker exists.
+async fn poll(store: &dyn Store, IndexState(mtx): &IndexState) -> Result<()> {
+    let generation = mtx.read().await.generation;
+
+    let pending = store
+        .get_object(&change_path(generation + 1))
+        .await
+        .optional()?
+        .is_some()
+        || load_marker(store).await? > generation;
+
+    if pending {
+        catch_up(store, &mut *mtx.write().await).await?;
+    }
+
+    Ok(())
+}
+
+/// Polls the store for changes from other replicas every `interval`.
+pub async fn follow(store: Arc<dyn Store>, index: IndexState, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        if let Err(err) = poll(store.as_ref(), &index).await {
+            eprintln!("applying index changes: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api, local::LocalStorage};
+
+    fn entry(name: &str, vers: &str) -> IndexEntry {
+        IndexEntry::for_test(api::CrateMeta::for_test(name, vers))
+    }
+
+    #[tokio::test]
+    async fn replicas_see_each_others_changes() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        let mut a = Index::default();
+        let mut b = Index::default();
+
+        commit(&store, &mut a, entry("foo", "0.1.0")).await.unwrap();
+
+        // `b` is behind, so its first attempt conflicts and it catches up.
+        commit(&store, &mut b, entry("bar", "0.1.0")).await.unwrap();
+        assert_eq!(b.generation, 2);
+        assert!(b.get_crate("foo").is_some());
+
+        catch_up(&store, &mut a).await.unwrap();
+        assert_eq!(a.generation, 2);
+        assert!(a.get_crate("bar").is_some());
+
+        let loaded = store.load_index().await.unwrap();
+        assert_eq!(loaded.generation, 2);
+    }
+
+    #[tokio::test]
+    async fn followers_ignore_stale_markers() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        let mut a = Index::default();
+        let b = IndexState(Arc::default());
+
+        commit(&store, &mut a, entry("foo", "0.1.0")).await.unwrap();
+        poll(&store, &b).await.unwrap();
+        assert_eq!(b.0.read().await.generation, 1);
+
+        commit(&store, &mut a, entry("bar", "0.1.0")).await.unwrap();
+
+        // A slow replica overwrote the marker with its older generation.
+        store
+            .put_object(GENERATION_PATH, Bytes::from_static(b"1"))
+            .await
+            .unwrap();
+
+        poll(&store, &b).await.unwrap();
+        assert_eq!(b.0.read().await.generation, 2);
+        assert!(b.0.read().await.get_crate("bar").is_some());
+    }
+}
@@ -0,0 +1,182 @@
+//! Download counts per crate version.
+//!
+//! Downloads are counted in memory and periodically added to a single counts
+//! object in the store. Instances sharing a store pick up each other's counts
+//! on their next flush. Two instances flushing at the same moment can lose a
+//! few counts, so with several instances the numbers are approximate.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use semver::Version;
+use tokio::sync::RwLock;
+
+use crate::{Result, error::Optional, store::Store};
+
+static DOWNLOADS_PATH: &str = "downloads/counts.json";
+
+type Counts = HashMap<String, BTreeMap<Version, u64>>;
+
+#[derive(Default)]
+pub struct Downloads {
+    /// Counts as of the last flush.
+    stored: RwLock<Counts>,
+
+    /// Downloads since the last flush.
+    pending: Mutex<Counts>,
+}
+
+fn add(counts: &mut Counts, other: Counts) {
+    for (name, versions) in other {
+        let counts = counts.entry(name).or_default();
+
+        for (version, n) in versions {
+            *counts.entry(version).or_default() += n;
+        }
+    }
+}
+
+async fn load_counts(store: &dyn Store) -> Result<Counts> {
+    let Some(data) = store.get_object(DOWNLOADS_PATH).await.optional()? else {
+        return Ok(Counts::default());
+    };
+
+    Ok(serde_json::from_slice(&data)?)
+}
+
+impl Downloads {
+    pub async fn load(store: &dyn Store) -> Result<Self> {
+        Ok(Self {
+            stored: RwLock::new(load_counts(store).await?),
+            pending: Mutex::default(),
+        })
+    }
+
+    pub fn count(&self, crate_name: &str, version: &Version) {
+        let mut pending = self.pending.lock().expect("download counts poisoned");
+
+        *pending
+            .entry(crate_name.to_string())
+            .or_default()
+            .entry(version.clone())
+            .or_default() += 1;
+    }
+
+    /// The downloads of every version of a crate, including unflushed ones.
+    pub async fn versions(&self, crate_name: &str) -> BTreeMap<Version, u64> {
+        let mut versions = self
+            .stored
+            .read()
+            .await
+            .get(crate_name)
+            .cloned()
+            .unwrap_or_default();
+
+        let pending = self.pending.lock().expect("download counts poisoned");
+
+        for (version, n) in pending.get(crate_name).into_iter().flatten() {
+            *versions.entry(version.clone()).or_default() += n;
+        }
+
+        versions
+    }
+
+    /// The downloads of all crates, by crate name.
+    pub async fn totals(&self) -> HashMap<String, u64> {
+        let mut totals = HashMap::<String, u64>::new();
+
+        for (crate_name, versions) in self.stored.read().await.iter() {
+            *totals.entry(crate_name.clone()).or_default() += versions.values().sum::<u64>();
+        }
+
+        let pending = self.pending.lock().expect("download counts poisoned");
+
+        for (crate_name, versions) in pending.iter() {
+            *totals.entry(crate_name.clone()).or_default() += versions.values().sum::<u64>();
+        }
+
+        totals
+    }
+
+    /// Adds the pending counts to the counts in the store.
+    pub async fn flush(&self, store: &dyn Store) -> Result<()> {
+        let mut stored = self.stored.write().await;
+
+        let pending = std::mem::take(&mut *self.pending.lock().expect("download counts poisoned"));
+
+        let res = async {
+            let mut counts = load_counts(store).await?;
+            add(&mut counts, pending.clone());
+
+            let data = Bytes::from(serde_json::to_vec(&counts)?);
+            store.put_object(DOWNLOADS_PATH, data).await?;
+
+            Ok(counts)
+        }
+        .await;
+
+        match res {
+            Ok(counts) => {
+                *stored = counts;
+                Ok(())
+            }
+
+            // Keep the counts for the next attempt.
+            Err(err) => {
+                add(
+                    &mut self.pending.lock().expect("download counts poisoned"),
+                    pending,
+                );
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Flushes the download counts to the store every `interval`.
+pub async fn flush_every(downloads: Arc<Downloads>, store: Arc<dyn Store>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        if let Err(err) = downloads.flush(store.as_ref()).await {
+            eprintln!("storing download counts: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::LocalStorage;
+
+    #[tokio::test]
+    async fn counts_survive_restarts() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        let v1 = Version::new(0, 1, 0);
+        let v2 = Version::new(0, 2, 0);
+
+        let a = Downloads::load(&store).await.unwrap();
+        let b = Downloads::load(&store).await.unwrap();
+
+        a.count("foo", &v1);
+        a.count("foo", &v2);
+        b.count("foo", &v2);
+        assert_eq!(a.totals().await["foo"], 2);
+
+        a.flush(&store).await.unwrap();
+        b.flush(&store).await.unwrap();
+        assert_eq!(b.totals().await["foo"], 3);
+
+        let restarted = Downloads::load(&store).await.unwrap();
+        assert_eq!(restarted.versions("foo").await[&v2], 2);
+        assert!(!restarted.totals().await.contains_key("bar"));
+    }
+}
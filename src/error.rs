@@ -10,35 +10,88 @@ pub enum Error {
     #[error("Not found")]
     NotFound,
 
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("missing or invalid API token")]
+    Unauthorized,
+
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("conflicting concurrent write, please retry")]
+    Conflict,
+
     #[error("crate `{name}-{version}` is already published")]
     CrateExists { name: String, version: api::Version },
 
     #[error("S3: {0}")]
     S3(#[from] S3Error),
+
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("requested range not satisfiable")]
+    RangeNotSatisfiable,
+
+    #[error("upstream: {0}")]
+    Upstream(String),
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Upstream(err.to_string())
+    }
 }
 
+/// The error body cargo shows to the user, `{"errors":[{"detail":"..."}]}`.
 #[derive(serde::Serialize)]
 struct ErrorResponse {
-    description: String,
+    errors: [ErrorDetail; 1],
+}
+
+#[derive(serde::Serialize)]
+struct ErrorDetail {
+    detail: String,
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let status_code = match &self {
             Self::NotFound => http::StatusCode::NOT_FOUND,
-            Self::CrateExists { .. } => http::StatusCode::BAD_REQUEST,
-            Self::S3(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Conflict => http::StatusCode::CONFLICT,
+            Self::Unauthorized => http::StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => http::StatusCode::FORBIDDEN,
+            Self::BadRequest(_) | Self::CrateExists { .. } => http::StatusCode::BAD_REQUEST,
+            Self::S3(_) | Self::Io(_) | Self::Json(_) => http::StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Upstream(_) => http::StatusCode::BAD_GATEWAY,
+            Self::RangeNotSatisfiable => http::StatusCode::RANGE_NOT_SATISFIABLE,
         };
 
-        let description = self.to_string();
+        let detail = self.to_string();
 
-        eprintln!("{status_code}: {description}");
+        eprintln!("{status_code}: {detail}");
+
+        let body = ErrorResponse {
+            errors: [ErrorDetail { detail }],
+        };
+        let mut res = (status_code, Json(body)).into_response();
+
+        // Tells cargo to retry with its token for `auth-required` registries.
+        if status_code == http::StatusCode::UNAUTHORIZED {
+            res.headers_mut().insert(
+                http::header::WWW_AUTHENTICATE,
+                http::HeaderValue::from_static("Cargo"),
+            );
+        }
 
-        (status_code, Json(ErrorResponse { description })).into_response()
+        res
     }
 }
 
-#[allow(dead_code)]
 pub trait Optional<T, E>
 where
     Self: Sized,
@@ -50,6 +103,7 @@ impl<T> Optional<T, Error> for Result<T, Error> {
     fn optional(self) -> std::result::Result<Option<T>, Error> {
         match self {
             Ok(v) => Ok(Some(v)),
+            Err(Error::NotFound) => Ok(None),
             Err(Error::S3(S3Error::Non2xx {
                 status: Some(404), ..
             })) => Ok(None),
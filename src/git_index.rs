@@ -0,0 +1,252 @@
+//! Renders the index as a git repository in the layout of the crates.io
+//! index, for tools that don't speak the sparse protocol.
+//!
+//! The repository is written with the `git` command line tool, and can be
+//! served with `git daemon` or any other git server.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use bytes::Bytes;
+
+use crate::{IndexState, NdJson, Result, api, index, index::Index};
+
+/// The contents of every file in the repository, by path.
+pub struct Files {
+    generation: u64,
+    files: HashMap<PathBuf, Bytes>,
+}
+
+/// Renders `config.json` and the file of every crate. Cheap enough to run
+/// while holding the index lock, unlike writing the files out.
+pub fn render(index: &Index, config: &api::routes::Config) -> Result<Files> {
+    let mut files = HashMap::new();
+
+    files.insert(
+        PathBuf::from("config.json"),
+        Bytes::from(serde_json::to_vec_pretty(config)?),
+    );
+
+    for name in index.crate_names() {
+        let lines = index
+            .get_crate(name)
+            .into_iter()
+            .flatten()
+            .map(index::IndexEntry::index_line)
+            .collect();
+
+        files.insert(
+            PathBuf::from(index::index_path(name)),
+            Bytes::from(NdJson(lines)),
+        );
+    }
+
+    Ok(Files {
+        generation: index.generation,
+        files,
+    })
+}
+
+pub struct GitIndex {
+    dir: PathBuf,
+}
+
+impl GitIndex {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Replaces the contents of the repository with `files`, and commits if
+    /// anything changed.
+    pub async fn export(&self, files: Files) -> Result<()> {
+        let dir = self.dir.clone();
+        let Files { generation, files } = files;
+
+        tokio::task::spawn_blocking(move || write_files(&dir, &files))
+            .await
+            .map_err(io::Error::other)??;
+
+        if !self.dir.join(".git").exists() {
+            self.git(&["init", "--quiet"]).await?;
+        }
+
+        self.git(&["add", "--all"]).await?;
+
+        if self.git(&["diff", "--cached", "--quiet"]).await.is_ok() {
+            return Ok(());
+        }
+
+        let message = format!("Update index to generation {generation}");
+
+        self.git(&[
+            "-c",
+            "user.name=lagret",
+            "-c",
+            "user.email=lagret@localhost",
+            "commit",
+            "--quiet",
+            "--message",
+            &message,
+        ])
+        .await?;
+
+        // Lets plain HTTP file servers serve the repository as well.
+        self.git(&["update-server-info"]).await
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<()> {
+        let output = tokio::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .await?;
+
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "`git {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+fn write_files(dir: &Path, files: &HashMap<PathBuf, Bytes>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let mut existing = Vec::new();
+    collect_files(dir, dir, &mut existing)?;
+
+    for path in existing {
+        if !files.contains_key(&path) {
+            fs::remove_file(dir.join(path))?;
+        }
+    }
+
+    for (path, data) in files {
+        let path = dir.join(path);
+
+        if fs::read(&path).is_ok_and(|current| current == data.as_ref()) {
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, data)?;
+    }
+
+    Ok(())
+}
+
+/// Lists the files below `dir` relative to `root`, leaving out `.git`.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.file_name().is_some_and(|name| name == ".git") {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Exports the index whenever its generation changes, which covers publishes
+/// and yanks on this replica as well as changes picked up from others.
+pub async fn follow(git_index: GitIndex, IndexState(mtx): IndexState, config: api::routes::Config) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    let mut exported = None;
+
+    loop {
+        ticker.tick().await;
+
+        let files = {
+            let index = mtx.read().await;
+
+            if exported == Some(index.generation) {
+                continue;
+            }
+
+            match render(&index, &config) {
+                Ok(files) => files,
+                Err(err) => {
+                    eprintln!("rendering git index: {err}");
+                    continue;
+                }
+            }
+        };
+
+        let generation = files.generation;
+
+        match git_index.export(files).await {
+            Ok(()) => exported = Some(generation),
+            Err(err) => eprintln!("exporting git index: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexEntry;
+
+    #[test]
+    fn renders_crates_io_layout() {
+        let mut index = Index::default();
+
+        for (name, vers) in [("a", "0.1.0"), ("Serde", "1.0.0"), ("Serde", "1.1.0")] {
+            index.add_crate_meta(IndexEntry::for_test(api::CrateMeta::for_test(name, vers)));
+        }
+
+        let config = api::routes::Config {
+            dl: "https://dl.example.com/{crate}".into(),
+            api: "https://example.com".into(),
+            auth_required: true,
+        };
+
+        let Files { files, .. } = render(&index, &config).unwrap();
+
+        let mut paths = files
+            .keys()
+            .map(|path| path.to_str().unwrap())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["1/a", "config.json", "se/rd/serde"]);
+
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&files[Path::new("config.json")]).unwrap(),
+            serde_json::json!({
+                "dl": "https://dl.example.com/{crate}",
+                "api": "https://example.com",
+                "auth-required": true,
+            })
+        );
+
+        let lines = files[Path::new("se/rd/serde")]
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                serde_json::from_slice::<api::PublishedCrate>(line)
+                    .unwrap()
+                    .vers
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["1.0.0".parse().unwrap(), "1.1.0".parse().unwrap()]);
+    }
+}
@@ -1,20 +1,116 @@
 use semver::Version;
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use crate::api;
+use crate::{
+    api,
+    search::{SearchIndex, Sort},
+};
 
-type VersionMap = HashMap<Version, IndexEntry>;
+type VersionMap = BTreeMap<Version, IndexEntry>;
 type CrateMap = HashMap<String, VersionMap>;
 
+/// Crate names that are equal after this are considered the same crate.
+pub fn canonical_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// The directories of the registry index a crate's file is in: `1`, `2`,
+/// `3/a` or `ab/cd`. Keeps the case of `name`, which must be ASCII.
+pub fn index_prefix(name: &str) -> String {
+    match name.len() {
+        0..=2 => name.len().to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// The path of a crate's file in the registry index, relative to its root:
+/// `1/a`, `2/ab`, `3/a/abc` or `ab/cd/abcd`.
+pub fn index_path(name: &str) -> String {
+    let lower = name.to_ascii_lowercase();
+
+    format!("{}/{lower}", index_prefix(&lower))
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a Unix timestamp like `2024-05-01T12:00:00Z`, as crates.io does.
+pub fn rfc3339(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| time::OffsetDateTime::from_unix_timestamp(secs).ok())
+        .and_then(|time| {
+            time.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
 #[derive(Default)]
 pub struct Index {
     crates: CrateMap,
+
+    /// Maps lowercased names to the names crates were published with.
+    lowercase_names: HashMap<String, String>,
+
+    /// The last change from the store's change log applied to this index.
+    pub generation: u64,
+
+    /// Searches the newest version of every crate.
+    search: SearchIndex,
 }
 
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct IndexEntry {
     pub cksum: String,
     pub meta: api::CrateMeta,
     pub yanked: bool,
+
+    /// Unix timestamp of the last publish or (un)yank of this version. Missing
+    /// for versions stored before it was recorded.
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+}
+
+impl IndexEntry {
+    /// The line of this version in the crate's index file.
+    pub fn index_line(&self) -> api::PublishedCrate {
+        let meta = &self.meta;
+
+        api::PublishedCrate {
+            name: meta.name.clone(),
+            vers: meta.vers.clone(),
+            deps: meta.deps.iter().map(api::IndexDep::from).collect(),
+            cksum: self.cksum.clone(),
+            features: meta.features.clone(),
+            yanked: self.yanked,
+            links: meta.links.clone(),
+            v: 2,
+            features2: meta.features.clone(),
+            rust_version: meta.rust_version.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl IndexEntry {
+    /// A version as it is right after publishing, not yanked.
+    pub fn for_test(meta: api::CrateMeta) -> Self {
+        Self {
+            cksum: String::new(),
+            meta,
+            yanked: false,
+            updated_at: None,
+        }
+    }
 }
 
 impl Index {
@@ -22,7 +118,37 @@ impl Index {
         let name = entry.meta.name.clone();
         let version = entry.meta.vers.clone();
 
-        self.crates.entry(name).or_default().insert(version, entry);
+        self.lowercase_names
+            .insert(name.to_ascii_lowercase(), name.clone());
+        let versions = self.crates.entry(name).or_default();
+        versions.insert(version, entry);
+
+        if let Some((_, newest)) = versions.last_key_value() {
+            self.search.update(&newest.meta);
+        }
+    }
+
+    /// Looks up the name a crate was published with, ignoring case.
+    pub fn crate_name_ignore_case(&self, crate_name: &str) -> Option<&str> {
+        self.lowercase_names
+            .get(&crate_name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn crate_names(&self) -> impl Iterator<Item = &str> {
+        self.crates.keys().map(String::as_str)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.crates.values().flat_map(|versions| versions.values())
+    }
+
+    /// Clones all versions of a crate, oldest first, looking its name up
+    /// ignoring case.
+    pub fn clone_versions_ignore_case(&self, crate_name: &str) -> Option<Vec<IndexEntry>> {
+        let versions = self.crates.get(self.crate_name_ignore_case(crate_name)?)?;
+
+        Some(versions.values().cloned().collect())
     }
 
     pub fn get_crate<'a>(
@@ -44,27 +170,99 @@ impl Index {
             .and_then(|versions| versions.get(version))
     }
 
-    pub fn search_crates(&self, q: impl AsRef<str>, max_count: usize) -> api::SearchResult {
-        let iter = self
-            .crates
-            .iter()
-            .filter(|(name, _)| name.contains(q.as_ref()));
+    /// Finds an existing crate whose name only differs from `crate_name` in
+    /// case or in `-` versus `_`.
+    pub fn similar_crate_name(&self, crate_name: &str) -> Option<&str> {
+        let canonical = canonical_crate_name(crate_name);
 
-        let total = iter.clone().count();
-
-        let crates = iter
-            .filter_map(|(_, versions)| {
-                let max_version = versions.keys().max()?;
+        self.crates
+            .keys()
+            .find(|name| *name != crate_name && canonical_crate_name(name) == canonical)
+            .map(String::as_str)
+    }
 
+    /// Finds the versions of other crates depending on `crate_name` from this
+    /// registry, with each matching dependency. Looks at only the newest
+    /// version of every crate with `latest_only`, and skips yanked versions
+    /// with `exclude_yanked`.
+    pub fn reverse_dependencies<'a>(
+        &'a self,
+        crate_name: &'a str,
+        latest_only: bool,
+        exclude_yanked: bool,
+    ) -> impl Iterator<Item = (&'a IndexEntry, &'a api::CrateDep)> {
+        self.crates
+            .iter()
+            .filter(move |(name, _)| !name.eq_ignore_ascii_case(crate_name))
+            .flat_map(move |(_, versions)| {
                 versions
-                    .get(max_version)
-                    .map(|IndexEntry { meta, .. }| api::CrateListItem {
-                        name: meta.name.clone(),
-                        max_version: meta.vers.clone(),
-                        description: meta.description.clone().unwrap_or_else(String::new),
+                    .values()
+                    .rev()
+                    .filter(move |entry| !(exclude_yanked && entry.yanked))
+                    .take(if latest_only { 1 } else { usize::MAX })
+            })
+            .flat_map(move |entry| {
+                entry
+                    .meta
+                    .deps
+                    .iter()
+                    .filter(move |dep| {
+                        dep.registry.is_none() && dep.name.eq_ignore_ascii_case(crate_name)
                     })
+                    .map(move |dep| (entry, dep))
+            })
+    }
+
+    /// Searches crates and returns page `page` of the results, counting from
+    /// 1. `downloads` has the total downloads of every crate.
+    pub fn search_crates(
+        &self,
+        q: &str,
+        sort: Sort,
+        downloads: &HashMap<String, u64>,
+        page: usize,
+        per_page: usize,
+    ) -> api::SearchResult {
+        let scores = self.search.search(q);
+
+        let mut hits = scores
+            .into_iter()
+            .filter_map(|(name, score)| {
+                let versions = self.crates.get(name)?;
+                let (_, newest) = versions.last_key_value()?;
+                let updated_at = versions.values().filter_map(|e| e.updated_at).max();
+                let downloads = downloads.get(name).copied().unwrap_or_default();
+
+                Some((newest, score, updated_at, downloads))
+            })
+            .collect::<Vec<_>>();
+
+        let by_name = |a: &IndexEntry, b: &IndexEntry| {
+            canonical_crate_name(&a.meta.name).cmp(&canonical_crate_name(&b.meta.name))
+        };
+
+        hits.sort_by(|a, b| {
+            match sort {
+                Sort::Relevance => b.1.cmp(&a.1).then(b.3.cmp(&a.3)),
+                Sort::Alpha => std::cmp::Ordering::Equal,
+                Sort::Downloads => b.3.cmp(&a.3),
+                Sort::RecentUpdates => b.2.cmp(&a.2),
+            }
+            .then_with(|| by_name(a.0, b.0))
+        });
+
+        let total = hits.len();
+
+        let crates = hits
+            .into_iter()
+            .skip(page.saturating_sub(1).saturating_mul(per_page))
+            .take(per_page)
+            .map(|(entry, _, _, downloads)| api::CrateListItem {
+                name: entry.meta.name.clone(),
+                max_version: entry.meta.vers.clone(),
+                description: entry.meta.description.clone().unwrap_or_default(),
+                downloads,
             })
-            .take(max_count)
             .collect();
 
         api::SearchResult {
@@ -73,3 +271,57 @@ impl Index {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("Abc"), "3/a/abc");
+        assert_eq!(index_path("Serde_json"), "se/rd/serde_json");
+    }
+
+    fn entry(name: &str, vers: &str, yanked: bool, deps: &[&str]) -> IndexEntry {
+        IndexEntry {
+            yanked,
+            ..IndexEntry::for_test(api::CrateMeta {
+                deps: deps
+                    .iter()
+                    .map(|dep| api::CrateDep::for_test(dep, "^1"))
+                    .collect(),
+                ..api::CrateMeta::for_test(name, vers)
+            })
+        }
+    }
+
+    #[test]
+    fn reverse_dependencies() {
+        let mut index = Index::default();
+
+        index.add_crate_meta(entry("core", "1.0.0", false, &[]));
+        index.add_crate_meta(entry("app", "0.1.0", false, &["core"]));
+        index.add_crate_meta(entry("app", "0.2.0", false, &["Core", "other"]));
+        index.add_crate_meta(entry("app", "0.3.0", true, &[]));
+        index.add_crate_meta(entry("tool", "1.0.0", true, &["core"]));
+
+        let versions = |latest_only, exclude_yanked| {
+            let mut versions = index
+                .reverse_dependencies("core", latest_only, exclude_yanked)
+                .map(|(entry, _)| format!("{}@{}", entry.meta.name, entry.meta.vers))
+                .collect::<Vec<_>>();
+            versions.sort();
+            versions
+        };
+
+        assert_eq!(
+            versions(false, false),
+            ["app@0.1.0", "app@0.2.0", "tool@1.0.0"]
+        );
+        assert_eq!(versions(false, true), ["app@0.1.0", "app@0.2.0"]);
+        assert_eq!(versions(true, true), ["app@0.2.0"]);
+        assert_eq!(versions(true, false), ["tool@1.0.0"]);
+    }
+}
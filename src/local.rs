@@ -0,0 +1,351 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    Error, Result,
+    store::{ByteRange, ObjectStream, Store},
+};
+
+/// Keeps all objects as plain files below a root directory, mainly for
+/// running lagret without an S3 bucket.
+#[derive(Clone)]
+pub struct LocalStorage {
+    root: Arc<PathBuf>,
+
+    /// Makes conditional overwrites atomic. Only within this process, so a
+    /// directory can't be shared by several instances.
+    overwrite_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl LocalStorage {
+    pub fn from_env() -> Self {
+        let root = std::env::var("LAGRET_LOCAL_STORE_DIR").expect("LAGRET_LOCAL_STORE_DIR");
+
+        Self::new(root)
+    }
+
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Arc::new(root.into()),
+            overwrite_lock: Arc::default(),
+        }
+    }
+
+    /// Keys are partly taken from request paths, so anything that could
+    /// point outside the root is refused.
+    fn object_path(&self, key: &str) -> Result<PathBuf> {
+        let path = Path::new(key);
+
+        if key.is_empty()
+            || !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::BadRequest(format!("invalid object key `{key}`")));
+        }
+
+        Ok(self.root.join(path))
+    }
+}
+
+fn collect_keys(root: &Path, dir: &Path, keys: &mut Vec<String>) -> io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_keys(root, &path, keys)?;
+        } else if let Ok(rel) = path.strip_prefix(root) {
+            let key = rel
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            keys.push(key);
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl Store for LocalStorage {
+    async fn get_object(&self, key: &str) -> Result<Bytes> {
+        match tokio::fs::read(self.object_path(key)?).await {
+            Ok(data) => Ok(Bytes::from(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(Error::NotFound),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn put_object(&self, key: &str, data: Bytes) -> Result<()> {
+        let path = self.object_path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        tokio::fs::write(path, data).await?;
+
+        Ok(())
+    }
+
+    async fn put_object_if_absent(&self, key: &str, data: Bytes) -> Result<()> {
+        let path = self.object_path(key)?;
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut file = match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .await
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => return Err(Error::Conflict),
+            Err(err) => return Err(err.into()),
+        };
+
+        file.write_all(&data).await?;
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn get_object_tagged(&self, key: &str) -> Result<(Bytes, String)> {
+        let data = self.get_object(key).await?;
+        let tag = sha256::digest(data.as_ref());
+
+        Ok((data, tag))
+    }
+
+    async fn put_object_if_match(&self, key: &str, data: Bytes, tag: &str) -> Result<()> {
+        let _guard = self.overwrite_lock.lock().await;
+
+        match self.get_object_tagged(key).await {
+            Ok((_, current)) if current == tag => self.put_object(key, data).await,
+            Ok(_) | Err(Error::NotFound) => Err(Error::Conflict),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.object_path(key)?).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_object_stream(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectStream> {
+        let mut file = match tokio::fs::File::open(self.object_path(key)?).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(Error::NotFound),
+            Err(err) => return Err(err.into()),
+        };
+
+        let size = file.metadata().await?.len();
+        let range = range.map(|range| range.resolve(size)).transpose()?;
+        let (first, last) = range.unwrap_or((0, size.saturating_sub(1)));
+
+        file.seek(io::SeekFrom::Start(first)).await?;
+        let len = if size == 0 { 0 } else { last - first + 1 };
+
+        Ok(ObjectStream {
+            body: ReaderStream::new(file.take(len)).boxed(),
+            size,
+            range,
+        })
+    }
+
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let root = self.root.clone();
+        let prefix = prefix.to_string();
+
+        let mut keys = tokio::task::spawn_blocking(move || {
+            let mut keys = Vec::new();
+            collect_keys(&root, &root, &mut keys)?;
+
+            io::Result::Ok(keys)
+        })
+        .await
+        .map_err(io::Error::other)??;
+
+        keys.retain(|key| key.starts_with(&prefix));
+        keys.sort();
+
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api, store::crate_path};
+
+    #[tokio::test]
+    async fn store_and_reload_crate() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        let meta = api::CrateMeta::for_test("dummy", "0.1.0");
+        let version = meta.vers.clone();
+
+        let entry = store
+            .store_crate(meta, Bytes::from_static(b"crate data"))
+            .await
+            .expect("storing crate");
+
+        for (range, expected) in [
+            (None, &b"crate data"[..]),
+            (Some(ByteRange::FromTo(0, 4)), b"crate"),
+            (Some(ByteRange::From(6)), b"data"),
+            (Some(ByteRange::Last(2)), b"ta"),
+        ] {
+            let crate_file = store
+                .download("dummy", &version, range)
+                .await
+                .expect("downloading");
+
+            assert_eq!(crate_file.size, 10);
+
+            let data = crate_file
+                .body
+                .map(|chunk| chunk.expect("reading").to_vec())
+                .concat()
+                .await;
+            assert_eq!(data, expected);
+        }
+
+        assert!(matches!(
+            store
+                .download("dummy", &version, Some(ByteRange::From(10)))
+                .await,
+            Err(Error::RangeNotSatisfiable)
+        ));
+
+        // The first load rebuilds the index and stores a snapshot, the second
+        // one is served from that snapshot.
+        for _ in 0..2 {
+            let index = store.load_index().await.expect("loading index");
+            let loaded = index
+                .get_crate_version("dummy", &version)
+                .expect("crate in index");
+            assert_eq!(loaded.cksum, entry.cksum);
+        }
+
+        let keys = store.list_keys("index/").await.expect("listing keys");
+        assert_eq!(keys, ["index/snapshot.json.gz"]);
+
+        assert!(matches!(
+            store
+                .download("dummy", &"0.2.0".parse().unwrap(), None)
+                .await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn keys_stay_below_root() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path().join("store"));
+
+        for key in ["../secret", "crates/../../secret", "/etc/passwd", ""] {
+            assert!(matches!(
+                store.put_object(key, Bytes::from_static(b"x")).await,
+                Err(Error::BadRequest(_))
+            ));
+            assert!(matches!(
+                store.get_object(key).await,
+                Err(Error::BadRequest(_))
+            ));
+        }
+
+        assert!(!dir.path().join("secret").exists());
+    }
+
+    #[tokio::test]
+    async fn store_crate_never_overwrites() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+        let version = api::CrateMeta::for_test("dummy", "0.1.0").vers;
+
+        // An orphaned upload with other bytes blocks the version...
+        store
+            .put_object(&crate_path("dummy", &version), Bytes::from_static(b"other"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            store
+                .store_crate(
+                    api::CrateMeta::for_test("dummy", "0.1.0"),
+                    Bytes::from_static(b"data")
+                )
+                .await,
+            Err(Error::Conflict)
+        ));
+
+        // ...until it is cleaned up.
+        store.remove_orphans().await.unwrap();
+
+        store
+            .store_crate(
+                api::CrateMeta::for_test("dummy", "0.1.0"),
+                Bytes::from_static(b"data"),
+            )
+            .await
+            .expect("storing crate");
+
+        assert!(matches!(
+            store
+                .store_crate(
+                    api::CrateMeta::for_test("dummy", "0.1.0"),
+                    Bytes::from_static(b"data")
+                )
+                .await,
+            Err(Error::CrateExists { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn overwrites_only_unchanged_objects() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        store
+            .put_object("key", Bytes::from_static(b"one"))
+            .await
+            .unwrap();
+        let (_, tag) = store.get_object_tagged("key").await.unwrap();
+
+        store
+            .put_object_if_match("key", Bytes::from_static(b"two"), &tag)
+            .await
+            .expect("overwriting");
+
+        assert!(matches!(
+            store
+                .put_object_if_match("key", Bytes::from_static(b"three"), &tag)
+                .await,
+            Err(Error::Conflict)
+        ));
+        assert_eq!(store.get_object("key").await.unwrap(), "two");
+    }
+}
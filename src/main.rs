@@ -1,22 +1,44 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use axum::{Extension, Router, http::request::Parts, routing};
+use axum::{
+    Extension, Router,
+    extract::DefaultBodyLimit,
+    http::{Method, Uri},
+    routing,
+};
 use clap::{Parser, Subcommand};
 use tokio::sync::RwLock;
 
 mod api;
+mod auth;
+mod changelog;
+mod downloads;
 mod error;
+mod git_index;
 mod index;
+mod local;
+mod mirror;
 mod nd_json;
+mod owners;
+mod policy;
+mod readme;
+mod resolve;
 mod s3;
+mod search;
+mod settings;
 mod store;
+mod tarball;
+mod upstream;
+mod validate;
+mod web;
 
 use {
     error::Error,
     index::{Index, IndexEntry},
     nd_json::NdJson,
-    s3::S3Storage,
-    store::CrateFile,
+    settings::Settings,
+    store::StoreState,
+    upstream::Upstream,
 };
 
 type Result<T> = std::result::Result<T, Error>;
@@ -32,6 +54,71 @@ enum Command {
     Run,
     ListObjects,
     LoadIndex,
+
+    /// Rebuilds the index from every stored version and replaces the snapshot.
+    RebuildIndex,
+
+    /// Removes `.crate` files left behind by interrupted publishes. Only run
+    /// this while no publishes are in progress.
+    RemoveOrphans,
+
+    /// Creates an API token and prints it. Only its hash is stored.
+    CreateToken {
+        /// The user the token belongs to, used as login for crate ownership.
+        #[arg(long)]
+        name: String,
+
+        #[arg(long = "scope", value_enum, required = true)]
+        scopes: Vec<auth::Scope>,
+
+        /// Limits the token to these crates. A trailing `*` matches any suffix.
+        #[arg(long = "crate")]
+        crates: Vec<String>,
+    },
+
+    /// Revokes a previously created API token.
+    RevokeToken {
+        token: String,
+    },
+
+    /// Writes the index into a git repository in the layout of the
+    /// crates.io index, committing any changes.
+    ExportGitIndex {
+        dir: PathBuf,
+    },
+
+    /// Publishes crates from an upstream registry into the store, so that
+    /// builds using them work without the upstream.
+    Mirror {
+        /// Mirrors every registry package of a `Cargo.lock`.
+        #[arg(long)]
+        lockfile: Option<PathBuf>,
+
+        /// Reads the `.crate` files from this directory instead of
+        /// downloading them.
+        #[arg(long)]
+        crate_dir: Option<PathBuf>,
+
+        #[arg(long, default_value = "https://index.crates.io")]
+        upstream: String,
+
+        /// Crates to mirror, as `name@version`.
+        crates: Vec<String>,
+    },
+
+    /// Resolves the dependencies of a version within the registry and lists
+    /// problems like unresolvable requirements or yanked dependencies.
+    CheckDeps {
+        /// The version to check, as `name@version`.
+        #[arg(value_name = "CRATE")]
+        krate: String,
+
+        #[arg(long, value_delimiter = ',')]
+        features: Vec<String>,
+
+        #[arg(long)]
+        no_default_features: bool,
+    },
 }
 
 #[derive(Clone)]
@@ -39,7 +126,7 @@ pub struct IndexState(Arc<RwLock<Index>>);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let s3_storage = S3Storage::from_env().await;
+    let store = store::from_env().await;
 
     let args = Args::parse();
 
@@ -47,23 +134,180 @@ async fn main() -> anyhow::Result<()> {
         Command::Run => (),
 
         Command::ListObjects => {
-            s3_storage.list_objects().await?;
+            for key in store.list_keys("").await? {
+                println!("Object: {key}");
+            }
             return Ok(());
         }
 
         Command::LoadIndex => {
-            s3_storage.load_index().await?;
+            store.load_index().await?;
+            return Ok(());
+        }
+
+        Command::RebuildIndex => {
+            let index = store.rebuild_index().await?;
+            store.store_index_snapshot(&index).await?;
+            return Ok(());
+        }
+
+        Command::RemoveOrphans => {
+            store.remove_orphans().await?;
+            return Ok(());
+        }
+
+        Command::CreateToken {
+            name,
+            scopes,
+            crates,
+        } => {
+            let token = auth::Token::generate();
+            let stored = auth::Token {
+                name,
+                scopes,
+                crates,
+            };
+
+            store
+                .store_token(&auth::Token::hash(&token), &stored)
+                .await?;
+            println!("{token}");
+            return Ok(());
+        }
+
+        Command::RevokeToken { token } => {
+            store.delete_token(&auth::Token::hash(&token)).await?;
+            return Ok(());
+        }
+
+        Command::ExportGitIndex { dir } => {
+            let settings = Settings::from_env();
+
+            let mut index = store.load_index().await?;
+            changelog::catch_up(store.as_ref(), &mut index).await?;
+
+            let files = git_index::render(&index, &api::routes::Config::from(&settings))?;
+            git_index::GitIndex::new(dir).export(files).await?;
+            return Ok(());
+        }
+
+        Command::Mirror {
+            lockfile,
+            crate_dir,
+            upstream,
+            crates,
+        } => {
+            let mut packages = crates
+                .iter()
+                .map(|spec| mirror::parse_package(spec))
+                .collect::<Result<Vec<_>>>()?;
+
+            if let Some(lockfile) = lockfile {
+                packages.extend(mirror::lockfile_packages(
+                    &tokio::fs::read_to_string(lockfile).await?,
+                    &upstream,
+                )?);
+            }
+
+            let mut index = store.load_index().await?;
+            changelog::catch_up(store.as_ref(), &mut index).await?;
+
+            mirror::mirror(
+                store.as_ref(),
+                &mut index,
+                &Upstream::new(&upstream),
+                crate_dir.as_deref(),
+                &packages,
+            )
+            .await?;
+            return Ok(());
+        }
+
+        Command::CheckDeps {
+            krate,
+            features,
+            no_default_features,
+        } => {
+            let package = mirror::parse_package(&krate)?;
+
+            let mut index = store.load_index().await?;
+            changelog::catch_up(store.as_ref(), &mut index).await?;
+
+            let res = resolve::resolve(
+                &index,
+                &package.name,
+                &package.version,
+                &features,
+                !no_default_features,
+            )?;
+
+            for package in &res.packages {
+                println!("{} {}", package.name, package.version);
+            }
+
+            for issue in &res.issues {
+                eprintln!("Problem: {issue}");
+            }
+
+            if !res.issues.is_empty() {
+                anyhow::bail!("found {} problems", res.issues.len());
+            }
             return Ok(());
         }
     }
 
-    let listen_addr = std::env::var("LAGRET_LISTEN_ADDR").expect("env var LAGRET_LISTEN_ADDR");
+    let settings = Arc::new(Settings::from_env());
+    let mut index = store.load_index().await?;
+    changelog::catch_up(store.as_ref(), &mut index).await?;
 
-    let index = s3_storage.load_index().await?;
+    let index_state = IndexState(Arc::new(RwLock::new(index)));
+
+    if let Some(interval) = settings.sync_interval {
+        tokio::spawn(changelog::follow(
+            store.clone(),
+            index_state.clone(),
+            interval,
+        ));
+    }
+
+    if let Some(dir) = &settings.git_index_dir {
+        tokio::spawn(git_index::follow(
+            git_index::GitIndex::new(dir),
+            index_state.clone(),
+            api::routes::Config::from(settings.as_ref()),
+        ));
+    }
+
+    let downloads = Arc::new(downloads::Downloads::load(store.as_ref()).await?);
+
+    if let Some(interval) = settings.downloads_flush_interval {
+        tokio::spawn(downloads::flush_every(
+            downloads.clone(),
+            store.clone(),
+            interval,
+        ));
+    }
+
+    let upstream = settings
+        .upstream_index
+        .as_deref()
+        .map(|url| Arc::new(Upstream::new(url)));
 
     // build our application with a single route
     let app = Router::new()
+        .route("/", routing::get(web::crate_list))
+        // Below `/ui`, so that crate pages can't shadow the downloads of a
+        // crate named `crates`.
+        .route("/ui", routing::get(web::crate_list))
+        .route("/ui/crates/{name}", routing::get(web::crate_page))
+        .route(
+            "/ui/crates/{name}/{version}",
+            routing::get(web::crate_version_page),
+        )
         .route("/config.json", routing::get(api::routes::get_config))
+        .route("/1/{name}", routing::get(api::routes::get_crate))
+        .route("/2/{name}", routing::get(api::routes::get_crate))
+        .route("/3/{c}/{name}", routing::get(api::routes::get_crate))
         .route("/{s1}/{s2}/{name}", routing::get(api::routes::get_crate))
         .route(
             "/{crate_name}/{version}/download",
@@ -71,22 +315,95 @@ async fn main() -> anyhow::Result<()> {
         )
         .route(
             "/api/v1/crates/new",
-            routing::put(api::routes::publish_crate),
+            // The metadata JSON comes on top of the crate file itself.
+            routing::put(api::routes::publish_crate).layer(DefaultBodyLimit::max(
+                settings.crate_limits.max_crate_size as usize + (1 << 20),
+            )),
         )
         .route("/api/v1/crates", routing::get(api::routes::search_crates))
-        .layer(Extension(IndexState(Arc::new(RwLock::new(index)))))
-        .layer(Extension(s3_storage))
-        .fallback(fallback);
+        .route(
+            "/api/v1/crates/{name}",
+            routing::get(api::routes::get_crate_info),
+        )
+        .route(
+            "/api/v1/crates/{name}/versions",
+            routing::get(api::routes::list_versions),
+        )
+        .route(
+            "/api/v1/crates/{name}/{version}",
+            routing::get(api::routes::get_version),
+        )
+        .route(
+            "/api/v1/crates/{name}/{version}/readme",
+            routing::get(api::routes::get_readme),
+        )
+        .route(
+            "/api/v1/crates/{name}/{version}/resolve",
+            routing::get(api::routes::resolve_version),
+        )
+        .route(
+            "/api/v1/crates/{name}/downloads",
+            routing::get(api::routes::crate_downloads),
+        )
+        .route(
+            "/api/v1/crates/{name}/reverse_dependencies",
+            routing::get(api::routes::reverse_dependencies),
+        )
+        .route(
+            "/api/v1/crates/{name}/owners",
+            routing::get(api::routes::list_owners)
+                .put(api::routes::add_owners)
+                .delete(api::routes::remove_owners),
+        )
+        .route(
+            "/api/v1/crates/{name}/{version}/yank",
+            routing::delete(api::routes::yank_crate),
+        )
+        .route(
+            "/api/v1/crates/{name}/{version}/unyank",
+            routing::put(api::routes::unyank_crate),
+        )
+        .layer(Extension(index_state))
+        .layer(Extension(upstream))
+        .layer(Extension(downloads.clone()))
+        .layer(Extension(StoreState(store.clone())))
+        .layer(Extension(settings.clone()));
+
+    let app = if settings.path_prefix.is_empty() {
+        app
+    } else {
+        Router::new().nest(&settings.path_prefix, app)
+    }
+    .fallback(fallback);
 
     // run our app with hyper, listening globally on port 3000
+    let listen_addr = settings
+        .listen_addr
+        .as_deref()
+        .expect("env var LAGRET_LISTEN_ADDR");
     let listener = tokio::net::TcpListener::bind(listen_addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    downloads.flush(store.as_ref()).await?;
 
     Ok(())
 }
 
-async fn fallback(parts: Parts) -> &'static str {
-    eprintln!("fallback: {parts:#?}");
+async fn shutdown_signal() {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("installing SIGTERM handler");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate.recv() => (),
+    }
+}
+
+async fn fallback(method: Method, uri: Uri) -> &'static str {
+    // Only the request line, as the headers may carry API tokens.
+    eprintln!("fallback: {method} {uri}");
 
     "hello"
 }
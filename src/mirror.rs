@@ -0,0 +1,301 @@
+//! Imports crates from an upstream registry into the store, so that a fixed
+//! set of dependencies can be built without access to the upstream.
+//!
+//! Mirrored versions are published like any other, with the metadata of
+//! their upstream index entry, and are served from the store from then on.
+//! Mirrored crates get an empty owner list, so that no token can publish
+//! versions shadowing the upstream ones.
+
+use std::{collections::HashMap, path::Path};
+
+use bytes::Bytes;
+use semver::Version;
+
+use crate::{
+    Error, Result, api, changelog,
+    error::Optional,
+    index::Index,
+    owners::Owners,
+    store::Store,
+    upstream::{Upstream, UpstreamVersion},
+    validate,
+};
+
+pub struct Package {
+    pub name: String,
+    pub version: Version,
+
+    /// The checksum recorded in `Cargo.lock`, if any.
+    pub checksum: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<LockedPackage>,
+}
+
+#[derive(serde::Deserialize)]
+struct LockedPackage {
+    name: String,
+    version: Version,
+    source: Option<String>,
+    checksum: Option<String>,
+}
+
+/// Parses a `name@version` argument.
+pub fn parse_package(spec: &str) -> Result<Package> {
+    let invalid = || Error::BadRequest(format!("invalid crate `{spec}`, expected `name@version`"));
+
+    let (name, version) = spec.split_once('@').ok_or_else(invalid)?;
+
+    Ok(Package {
+        name: name.to_string(),
+        version: version.parse().map_err(|_| invalid())?,
+        checksum: None,
+    })
+}
+
+/// Lists the packages of a `Cargo.lock` that come from the registry at
+/// `index_url`. Path and git dependencies are skipped, as are packages of
+/// other registries, which the upstream might have different crates for.
+pub fn lockfile_packages(lockfile: &str, index_url: &str) -> Result<Vec<Package>> {
+    let lockfile = toml::from_str::<Lockfile>(lockfile)
+        .map_err(|err| Error::BadRequest(format!("parsing `Cargo.lock`: {err}")))?;
+
+    Ok(lockfile
+        .package
+        .into_iter()
+        .filter(|package| match package.source.as_deref() {
+            Some(source) if source.starts_with("registry+") || source.starts_with("sparse+") => {
+                let same = same_registry(source, index_url);
+
+                if !same {
+                    println!(
+                        "skipping {} {}, it is from {source}",
+                        package.name, package.version
+                    );
+                }
+
+                same
+            }
+
+            _ => false,
+        })
+        .map(|package| Package {
+            name: package.name,
+            version: package.version,
+            checksum: package.checksum,
+        })
+        .collect())
+}
+
+/// Whether two index URLs, with or without `registry+`/`sparse+`, are the
+/// same registry. `Cargo.lock` names crates.io by its git index even when
+/// cargo uses the sparse one.
+fn same_registry(a: &str, b: &str) -> bool {
+    fn normalize(url: &str) -> &str {
+        url.trim_start_matches("registry+")
+            .trim_start_matches("sparse+")
+            .trim_end_matches('/')
+    }
+
+    let is_crates_io = |url| {
+        validate::CRATES_IO_INDEX
+            .iter()
+            .any(|crates_io| normalize(crates_io) == url)
+    };
+
+    let (a, b) = (normalize(a), normalize(b));
+
+    a == b || is_crates_io(a) && is_crates_io(b)
+}
+
+/// Publishes `packages` from the upstream, skipping versions that are in
+/// the index already. With `crate_dir`, the `.crate` files are read from
+/// there instead of being downloaded, but the metadata still comes from the
+/// upstream index.
+pub async fn mirror(
+    store: &dyn Store,
+    index: &mut Index,
+    upstream: &Upstream,
+    crate_dir: Option<&Path>,
+    packages: &[Package],
+) -> Result<()> {
+    let mut index_files = HashMap::new();
+
+    for package in packages {
+        let Package {
+            name,
+            version,
+            checksum,
+        } = package;
+
+        if index.get_crate_version(name, version).is_some() {
+            println!("{name} {version} is mirrored already");
+            continue;
+        }
+
+        if !index_files.contains_key(name) {
+            let index_file = upstream.fetch_index_file(name).await.optional()?;
+            index_files.insert(name.clone(), index_file.unwrap_or_default());
+        }
+
+        let published = find_version(&index_files[name], version).ok_or_else(|| {
+            Error::BadRequest(format!("`{name}-{version}` is not in the upstream index"))
+        })?;
+
+        if checksum
+            .as_ref()
+            .is_some_and(|cksum| *cksum != published.cksum)
+        {
+            return Err(Error::BadRequest(format!(
+                "checksum of `{name}-{version}` in `Cargo.lock` differs from the upstream index"
+            )));
+        }
+
+        let data = match crate_dir {
+            Some(dir) => {
+                let data = Bytes::from(
+                    tokio::fs::read(dir.join(format!("{name}-{version}.crate"))).await?,
+                );
+
+                if sha256::digest(data.as_ref()) != published.cksum {
+                    return Err(Error::BadRequest(format!(
+                        "`{name}-{version}.crate` in `{}` doesn't match the upstream checksum",
+                        dir.display()
+                    )));
+                }
+
+                data
+            }
+
+            None => {
+                upstream
+                    .fetch_crate(name, version, &published.cksum)
+                    .await?
+            }
+        };
+
+        match store.create_owners(name, &Owners::default()).await {
+            // Owned already, e.g. as an earlier version was mirrored.
+            Ok(()) | Err(Error::Conflict) => (),
+            Err(err) => return Err(err),
+        }
+
+        let yanked = published.yanked;
+
+        let mut entry = match store.store_crate(crate_meta(published), data).await {
+            Ok(entry) => entry,
+
+            Err(err @ Error::CrateExists { .. }) => {
+                println!("skipping: {err}");
+                continue;
+            }
+
+            Err(err) => return Err(err),
+        };
+
+        if yanked {
+            entry.yanked = true;
+            store.update_crate_meta(&entry).await?;
+        }
+
+        if let Err(err) = changelog::commit(store, index, entry).await {
+            store.remove_crate(name, version).await?;
+            return Err(err);
+        }
+
+        println!("mirrored {name} {version}");
+    }
+
+    Ok(())
+}
+
+fn find_version(index_file: &[u8], version: &Version) -> Option<api::PublishedCrate> {
+    index_file
+        .split(|b| *b == b'\n')
+        .find(|line| {
+            serde_json::from_slice::<UpstreamVersion>(line).is_ok_and(|v| v.vers == *version)
+        })
+        .and_then(|line| serde_json::from_slice(line).ok())
+}
+
+/// The publish metadata of an upstream index entry. The index doesn't have
+/// descriptions, authors and the like, so those stay empty.
+fn crate_meta(published: api::PublishedCrate) -> api::CrateMeta {
+    let mut features = published.features;
+    features.extend(published.features2);
+
+    api::CrateMeta {
+        name: published.name,
+        vers: published.vers,
+        deps: published
+            .deps
+            .into_iter()
+            .map(api::CrateDep::from)
+            .collect(),
+        features,
+        authors: Vec::new(),
+        description: None,
+        documentation: None,
+        homepage: None,
+        readme: None,
+        readme_file: None,
+        keywords: Vec::new(),
+        categories: Vec::new(),
+        license: None,
+        license_file: None,
+        repository: None,
+        badges: HashMap::new(),
+        links: published.links,
+        rust_version: published.rust_version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_packages_of_lockfile() {
+        let lockfile = r#"
+version = 4
+
+[[package]]
+name = "app"
+version = "0.1.0"
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "abc"
+
+[[package]]
+name = "internal"
+version = "0.3.0"
+source = "sparse+https://crates.example.com/index/"
+
+[[package]]
+name = "vendored"
+version = "0.2.0"
+source = "git+https://example.com/vendored#0123"
+"#;
+
+        let packages = lockfile_packages(lockfile, "https://index.crates.io").unwrap();
+
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "serde");
+        assert_eq!(packages[0].checksum.as_deref(), Some("abc"));
+
+        let packages =
+            lockfile_packages(lockfile, "sparse+https://crates.example.com/index").unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].name, "internal");
+
+        let package = parse_package("serde@1.0.200").unwrap();
+        assert_eq!(package.version, Version::new(1, 0, 200));
+        assert!(parse_package("serde").is_err());
+    }
+}
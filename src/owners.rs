@@ -0,0 +1,127 @@
+use crate::{Error, Result, auth::Token, error::Optional, store::Store};
+
+/// The logins owning a crate, as stored next to its version metadata.
+#[derive(Default, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Owners {
+    pub users: Vec<String>,
+}
+
+impl Owners {
+    pub fn contains(&self, login: &str) -> bool {
+        self.users.iter().any(|user| user == login)
+    }
+}
+
+/// Fails unless the token belongs to an owner of the crate. Crates published
+/// before ownership was recorded have no owners and are open to every token
+/// with the right scope.
+pub async fn require_owner(store: &dyn Store, token: &Token, crate_name: &str) -> Result<()> {
+    match store.load_owners(crate_name).await.optional()? {
+        Some(owners) if !owners.contains(&token.name) => Err(Error::Forbidden(format!(
+            "`{}` is not an owner of crate `{crate_name}`",
+            token.name
+        ))),
+
+        _ => Ok(()),
+    }
+}
+
+/// Applies `change` to the stored owners of a crate. When another request
+/// changed them in the meantime, it starts over from their new state, so
+/// that concurrent updates don't overwrite each other.
+pub async fn update(
+    store: &dyn Store,
+    crate_name: &str,
+    change: impl Fn(&mut Owners) -> Result<()>,
+) -> Result<()> {
+    loop {
+        let (mut owners, tag) = match store.load_owners_tagged(crate_name).await.optional()? {
+            Some((owners, tag)) => (owners, Some(tag)),
+            None => (Owners::default(), None),
+        };
+
+        change(&mut owners)?;
+
+        let res = match &tag {
+            Some(tag) => store.replace_owners(crate_name, &owners, tag).await,
+            None => store.create_owners(crate_name, &owners).await,
+        };
+
+        match res {
+            Err(Error::Conflict) => continue,
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::LocalStorage;
+
+    fn token(name: &str) -> Token {
+        Token {
+            name: name.into(),
+            scopes: Vec::new(),
+            crates: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_owners_pass() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        // Without recorded owners every token passes.
+        require_owner(&store, &token("bob"), "foo").await.unwrap();
+
+        update(&store, "foo", |owners| {
+            owners.users.push("alice".into());
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        require_owner(&store, &token("alice"), "foo").await.unwrap();
+        assert!(matches!(
+            require_owner(&store, &token("bob"), "foo").await,
+            Err(Error::Forbidden(_))
+        ));
+
+        // An empty owner list, as mirrored crates get, lets nobody in.
+        store
+            .create_owners("bar", &Owners::default())
+            .await
+            .unwrap();
+        assert!(matches!(
+            require_owner(&store, &token("alice"), "bar").await,
+            Err(Error::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn update_builds_on_stored_owners() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        for user in ["alice", "bob"] {
+            update(&store, "foo", |owners| {
+                owners.users.push(user.into());
+                Ok(())
+            })
+            .await
+            .unwrap();
+        }
+
+        // A failing change leaves the stored owners alone.
+        let res = update(&store, "foo", |owners| {
+            owners.users.clear();
+            Err(Error::BadRequest("no".into()))
+        })
+        .await;
+        assert!(matches!(res, Err(Error::BadRequest(_))));
+
+        let owners = store.load_owners("foo").await.unwrap();
+        assert_eq!(owners.users, ["alice", "bob"]);
+    }
+}
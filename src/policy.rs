@@ -0,0 +1,270 @@
+//! Organisation specific rules for published crates, on top of the checks in
+//! [`validate`](crate::validate). Configured in a TOML file:
+//!
+//! ```toml
+//! mode = "warn"
+//! require_license = true
+//! allowed_licenses = ["MIT", "Apache-2.0"]
+//! forbid_crates_io_dependencies = true
+//! min_rust_version = "1.75"
+//! ```
+
+use semver::Version;
+
+use crate::{Error, Result, api, validate};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Rejects publishes violating the policy.
+    #[default]
+    Enforce,
+
+    /// Publishes anyway and returns the violations as warnings to cargo.
+    Warn,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub mode: Mode,
+
+    pub require_license: bool,
+    pub require_repository: bool,
+
+    /// Every license named in a crate's `license` expression has to be one
+    /// of these. A `license-file` alone doesn't do then.
+    pub allowed_licenses: Option<Vec<String>>,
+
+    pub forbid_crates_io_dependencies: bool,
+
+    /// Index URLs of the only other registries dependencies may come from.
+    pub allowed_registries: Option<Vec<String>>,
+
+    /// Requires `rust-version` to be set and at least this, like `1.75`.
+    pub min_rust_version: Option<String>,
+
+    /// Forbids `links`, i.e. linking native libraries from build scripts.
+    pub forbid_links: bool,
+}
+
+impl Policy {
+    /// Reads a policy file, panicking on errors like the rest of the settings.
+    pub fn load(path: &str) -> Self {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("reading policy file `{path}`: {err}"));
+
+        let policy = toml::from_str::<Self>(&text)
+            .unwrap_or_else(|err| panic!("invalid policy file `{path}`: {err}"));
+
+        if let Some(min) = &policy.min_rust_version {
+            parse_rust_version(min)
+                .unwrap_or_else(|| panic!("invalid `min_rust_version` `{min}` in `{path}`"));
+        }
+
+        policy
+    }
+
+    /// Checks a publish, returning an error or adding warnings depending on
+    /// the mode.
+    pub fn apply(&self, meta: &api::CrateMeta, warnings: &mut api::PublishWarnings) -> Result<()> {
+        let violations = self.violations(meta);
+
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        match self.mode {
+            Mode::Enforce => Err(Error::BadRequest(format!(
+                "crate violates the registry's publish policy: {}",
+                violations.join("; ")
+            ))),
+
+            Mode::Warn => {
+                warnings.other.extend(violations);
+                Ok(())
+            }
+        }
+    }
+
+    fn violations(&self, meta: &api::CrateMeta) -> Vec<String> {
+        let mut violations = Vec::new();
+
+        let license = validate::non_blank(meta.license.as_deref());
+        let license_file = validate::non_blank(meta.license_file.as_deref());
+
+        if self.require_license && license.is_none() && license_file.is_none() {
+            violations.push("`license` or `license-file` is required".to_string());
+        }
+
+        if self.require_repository && meta.repository.as_deref().is_none_or(str::is_empty) {
+            violations.push("`repository` is required".to_string());
+        }
+
+        if let Some(allowed) = &self.allowed_licenses {
+            match license {
+                Some(license) => {
+                    for id in license_ids(license) {
+                        if !allowed
+                            .iter()
+                            .any(|allowed| allowed.eq_ignore_ascii_case(id))
+                        {
+                            violations.push(format!("license `{id}` is not allowed"));
+                        }
+                    }
+                }
+
+                None if license_file.is_some() => violations.push(
+                    "`license` must name an allowed license, a `license-file` is not enough"
+                        .to_string(),
+                ),
+
+                // Reported by `require_license` if wanted.
+                None => (),
+            }
+        }
+
+        for dep in &meta.deps {
+            let Some(registry) = &dep.registry else {
+                continue;
+            };
+
+            if self.forbid_crates_io_dependencies
+                && validate::CRATES_IO_INDEX.contains(&registry.as_str())
+            {
+                violations.push(format!("dependency `{}` is from crates.io", dep.name));
+            } else if let Some(allowed) = &self.allowed_registries
+                && !allowed.contains(registry)
+            {
+                violations.push(format!(
+                    "dependency `{}` is from registry `{registry}`, which is not allowed",
+                    dep.name
+                ));
+            }
+        }
+
+        if let Some(min) = self.min_rust_version.as_deref() {
+            let min_version = parse_rust_version(min);
+
+            match meta.rust_version.as_deref() {
+                None => violations.push(format!("`rust-version` is required, at least {min}")),
+
+                Some(rust_version) if parse_rust_version(rust_version) < min_version => violations
+                    .push(format!(
+                        "`rust-version` {rust_version} is older than the required {min}"
+                    )),
+
+                Some(_) => (),
+            }
+        }
+
+        if self.forbid_links
+            && let Some(links) = &meta.links
+        {
+            violations.push(format!("`links = \"{links}\"` is not allowed"));
+        }
+
+        violations
+    }
+}
+
+/// Parses `1.75` or `1.75.0` as found in `rust-version`.
+fn parse_rust_version(version: &str) -> Option<Version> {
+    let mut parts = version.trim().split('.').map(str::parse::<u64>);
+
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+
+    parts
+        .next()
+        .is_none()
+        .then(|| Version::new(major, minor, patch))
+}
+
+/// The license identifiers of an SPDX expression like
+/// `(MIT OR Apache-2.0) AND Unicode-3.0`, without `WITH` exceptions.
+fn license_ids(expression: &str) -> Vec<&str> {
+    let mut ids = Vec::new();
+    let mut tokens = expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '/')
+        .filter(|token| !token.is_empty());
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "OR" | "AND" => (),
+            "WITH" => {
+                tokens.next();
+            }
+            id => ids.push(id),
+        }
+    }
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_violations() {
+        let policy = toml::from_str::<Policy>(
+            r#"
+            mode = "warn"
+            require_repository = true
+            allowed_licenses = ["MIT", "Apache-2.0"]
+            forbid_crates_io_dependencies = true
+            min_rust_version = "1.75"
+            forbid_links = true
+            "#,
+        )
+        .unwrap();
+
+        let meta = api::CrateMeta {
+            deps: vec![api::CrateDep {
+                registry: Some("https://github.com/rust-lang/crates.io-index".into()),
+                ..api::CrateDep::for_test("serde", "^1")
+            }],
+            license: Some("(MIT OR Apache-2.0) AND GPL-3.0 WITH Classpath-exception-2.0".into()),
+            links: Some("z".into()),
+            rust_version: Some("1.70".into()),
+            ..api::CrateMeta::for_test("foo", "0.1.0")
+        };
+
+        let mut warnings = api::PublishWarnings::default();
+        policy.apply(&meta, &mut warnings).unwrap();
+
+        assert_eq!(
+            warnings.other,
+            [
+                "`repository` is required",
+                "license `GPL-3.0` is not allowed",
+                "dependency `serde` is from crates.io",
+                "`rust-version` 1.70 is older than the required 1.75",
+                "`links = \"z\"` is not allowed",
+            ]
+        );
+
+        let enforced = Policy {
+            mode: Mode::Enforce,
+            ..policy
+        };
+        assert!(enforced.apply(&meta, &mut warnings).is_err());
+        assert!(Policy::default().apply(&meta, &mut warnings).is_ok());
+
+        let licensed = Policy {
+            require_license: true,
+            ..Policy::default()
+        };
+        let unlicensed = api::CrateMeta {
+            license: Some(" ".into()),
+            license_file: Some(String::new()),
+            ..meta
+        };
+        assert_eq!(
+            licensed.violations(&unlicensed),
+            ["`license` or `license-file` is required"]
+        );
+    }
+}
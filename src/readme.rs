@@ -0,0 +1,53 @@
+//! Rendering of crate READMEs for browsers.
+
+use pulldown_cmark::{Options, Parser};
+
+/// Whether a README file is Markdown, going by its name. READMEs without an
+/// extension are assumed to be Markdown, like crates.io does.
+pub fn is_markdown(readme_file: &str) -> bool {
+    match readme_file.rsplit_once('.') {
+        Some((_, ext)) => matches!(
+            ext.to_ascii_lowercase().as_str(),
+            "md" | "markdown" | "mdown" | "mkdn"
+        ),
+        None => true,
+    }
+}
+
+/// Renders a README as HTML that is safe to embed in a page. Anything but
+/// Markdown is shown as preformatted text.
+pub fn render_html(readme: &str, readme_file: &str) -> String {
+    if !is_markdown(readme_file) {
+        return format!("<pre>{}</pre>", ammonia::clean_text(readme));
+    }
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Parser::new_ext(readme, options));
+
+    ammonia::clean(&html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_sanitized_html() {
+        let html = render_html(
+            "# Title\n\n<script>alert(1)</script>\n\n[x](javascript:alert(1))",
+            "README.md",
+        );
+
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("javascript:"));
+
+        let text = render_html("<b>", "README.txt");
+        assert!(text.starts_with("<pre>") && text.contains("&lt;b&gt;"));
+    }
+}
@@ -0,0 +1,493 @@
+//! Resolves the dependency graph of a version against the index, like cargo
+//! would for a crate depending on it, to find problems before a release.
+//!
+//! Unlike cargo's resolver this one doesn't backtrack: a requirement that
+//! cargo might still satisfy by picking an older version of a crate used
+//! elsewhere is reported as a conflict. Dev dependencies are skipped, and
+//! dependencies for every target are followed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+};
+
+use semver::Version;
+
+use crate::{
+    Error, Result,
+    api::{self, CrateDepKind, ResolveIssue},
+    index::{Index, IndexEntry},
+};
+
+struct Resolver<'a> {
+    index: &'a Index,
+
+    /// The versions in the graph by crate name, with the features enabled
+    /// on them.
+    selected: BTreeMap<String, BTreeMap<Version, BTreeSet<String>>>,
+
+    /// Versions whose dependencies need to be (re)visited, also kept in
+    /// `queued` to check quickly.
+    queue: Vec<(String, Version)>,
+    queued: HashSet<(String, Version)>,
+    issues: Vec<ResolveIssue>,
+}
+
+/// Resolves `name@version` with `features` enabled, plus its default features
+/// with `default_features`.
+pub fn resolve(
+    index: &Index,
+    name: &str,
+    version: &Version,
+    features: &[String],
+    default_features: bool,
+) -> Result<api::Resolution> {
+    let name = index.crate_name_ignore_case(name).ok_or(Error::NotFound)?;
+    index
+        .get_crate_version(name, version)
+        .ok_or(Error::NotFound)?;
+
+    let mut resolver = Resolver {
+        index,
+        selected: BTreeMap::new(),
+        queue: Vec::new(),
+        queued: HashSet::new(),
+        issues: Vec::new(),
+    };
+
+    resolver.activate(name, version, features.iter().cloned(), default_features);
+
+    while let Some(key) = resolver.queue.pop() {
+        resolver.queued.remove(&key);
+        resolver.process(&key.0, &key.1);
+    }
+
+    for (name, versions) in &resolver.selected {
+        if versions.len() > 1 {
+            resolver.issues.push(ResolveIssue::DuplicateMajor {
+                dependency: name.clone(),
+                versions: versions.keys().cloned().collect(),
+            });
+        }
+    }
+
+    Ok(api::Resolution {
+        packages: resolver
+            .selected
+            .into_iter()
+            .flat_map(|(name, versions)| {
+                versions
+                    .into_iter()
+                    .map(move |(version, features)| api::ResolvedPackage {
+                        name: name.clone(),
+                        version,
+                        features: features.into_iter().collect(),
+                    })
+            })
+            .collect(),
+        issues: resolver.issues,
+    })
+}
+
+/// Versions in the same group are semver compatible, cargo only uses one
+/// version of a crate per group.
+fn compat_group(version: &Version) -> (u64, u64, u64) {
+    match (version.major, version.minor) {
+        (0, 0) => (0, 0, version.patch),
+        (0, minor) => (0, minor, 0),
+        (major, _) => (major, 0, 0),
+    }
+}
+
+impl Resolver<'_> {
+    fn issue(&mut self, issue: ResolveIssue) {
+        if !self.issues.contains(&issue) {
+            self.issues.push(issue);
+        }
+    }
+
+    /// Enables features of a version, queueing it if anything changed.
+    fn activate(
+        &mut self,
+        name: &str,
+        version: &Version,
+        features: impl IntoIterator<Item = String>,
+        default_features: bool,
+    ) {
+        let versions = self.selected.entry(name.to_string()).or_default();
+        let mut changed = !versions.contains_key(version);
+        let enabled = versions.entry(version.clone()).or_default();
+
+        for feature in features
+            .into_iter()
+            .chain(default_features.then(|| "default".to_string()))
+        {
+            changed |= enabled.insert(feature);
+        }
+
+        let key = (name.to_string(), version.clone());
+
+        if changed && self.queued.insert(key.clone()) {
+            self.queue.push(key);
+        }
+    }
+
+    fn process(&mut self, name: &str, version: &Version) {
+        let Some(entry) = self.index.get_crate_version(name, version) else {
+            return;
+        };
+        let package = format!("{name}@{version}");
+        let requested = self.selected[name][version].clone();
+
+        let (active_deps, dep_features) = self.expand_features(entry, &package, requested);
+
+        for dep in &entry.meta.deps {
+            let toml_name = dep.explicit_name_in_toml.as_deref().unwrap_or(&dep.name);
+
+            if matches!(dep.kind, CrateDepKind::Dev)
+                || dep.optional && !active_deps.contains(toml_name)
+            {
+                continue;
+            }
+
+            if let Some(registry) = &dep.registry {
+                self.issue(ResolveIssue::OtherRegistry {
+                    package: package.clone(),
+                    dependency: dep.name.clone(),
+                    registry: registry.clone(),
+                });
+                continue;
+            }
+
+            let Some(selected) = self.select(&package, dep) else {
+                continue;
+            };
+
+            let features = dep
+                .features
+                .iter()
+                .cloned()
+                .chain(dep_features.get(toml_name).into_iter().flatten().cloned())
+                .collect::<Vec<_>>();
+
+            self.activate(&selected.0, &selected.1, features, dep.default_features);
+        }
+    }
+
+    /// Follows the feature table of a version, returning the optional
+    /// dependencies the features enable and the features they enable on
+    /// dependencies, both by their name in `Cargo.toml`.
+    fn expand_features<'e>(
+        &mut self,
+        entry: &'e IndexEntry,
+        package: &str,
+        requested: BTreeSet<String>,
+    ) -> (HashSet<&'e str>, HashMap<&'e str, BTreeSet<String>>) {
+        let meta = &entry.meta;
+
+        let mut active_deps = HashSet::new();
+        let mut dep_features = HashMap::<&str, BTreeSet<String>>::new();
+        let mut weak = Vec::new();
+
+        // Optional dependencies are implicit features, unless a feature
+        // refers to them with `dep:`.
+        let explicit = meta
+            .features
+            .values()
+            .flatten()
+            .filter_map(|item| item.strip_prefix("dep:"))
+            .collect::<HashSet<_>>();
+        let implicit = meta
+            .deps
+            .iter()
+            .filter(|dep| dep.optional)
+            .map(|dep| dep.explicit_name_in_toml.as_deref().unwrap_or(&dep.name))
+            .filter(|name| !explicit.contains(name))
+            .collect::<HashSet<_>>();
+
+        let mut enabled = HashSet::new();
+        let mut todo = requested.into_iter().collect::<Vec<_>>();
+
+        while let Some(feature) = todo.pop() {
+            if !enabled.insert(feature.clone()) {
+                continue;
+            }
+
+            if let Some(items) = meta.features.get(&feature) {
+                for item in items {
+                    if let Some(dep) = item.strip_prefix("dep:") {
+                        active_deps.insert(dep);
+                    } else if let Some((dep, dep_feature)) = item.split_once('/') {
+                        if let Some(dep) = dep.strip_suffix('?') {
+                            weak.push((dep, dep_feature));
+                        } else {
+                            active_deps.insert(dep);
+                            dep_features
+                                .entry(dep)
+                                .or_default()
+                                .insert(dep_feature.to_string());
+                        }
+                    } else {
+                        todo.push(item.clone());
+                    }
+                }
+            } else if let Some(dep) = implicit.get(feature.as_str()) {
+                active_deps.insert(*dep);
+            } else if feature != "default" {
+                self.issue(ResolveIssue::UnknownFeature {
+                    package: package.to_string(),
+                    feature,
+                });
+            }
+        }
+
+        // `dep?/feature` only applies if something else enabled the dependency.
+        for (dep, dep_feature) in weak {
+            if active_deps.contains(dep) {
+                dep_features
+                    .entry(dep)
+                    .or_default()
+                    .insert(dep_feature.to_string());
+            }
+        }
+
+        (active_deps, dep_features)
+    }
+
+    fn selected_versions(&self, name: &str) -> impl Iterator<Item = &Version> {
+        self.selected.get(name).into_iter().flat_map(BTreeMap::keys)
+    }
+
+    /// Picks the version to use for a dependency: one already selected if it
+    /// matches, or else the newest match, preferring versions not yanked.
+    fn select(&mut self, package: &str, dep: &api::CrateDep) -> Option<(String, Version)> {
+        let unresolvable = || ResolveIssue::Unresolvable {
+            package: package.to_string(),
+            dependency: dep.name.clone(),
+            req: dep.version_req.clone(),
+        };
+
+        let Some(name) = self.index.crate_name_ignore_case(&dep.name) else {
+            self.issue(unresolvable());
+            return None;
+        };
+
+        if let Some(version) = self
+            .selected_versions(name)
+            .find(|version| dep.version_req.matches(version))
+        {
+            return Some((name.to_string(), version.clone()));
+        }
+
+        let matching = self
+            .index
+            .get_crate(name)
+            .into_iter()
+            .flatten()
+            .filter(|entry| dep.version_req.matches(&entry.meta.vers))
+            .collect::<Vec<_>>();
+
+        let Some(entry) = matching
+            .iter()
+            .rev()
+            .find(|entry| !entry.yanked)
+            .or(matching.last())
+        else {
+            self.issue(unresolvable());
+            return None;
+        };
+        let version = &entry.meta.vers;
+
+        if entry.yanked {
+            self.issue(ResolveIssue::Yanked {
+                package: package.to_string(),
+                dependency: name.to_string(),
+                version: version.clone(),
+            });
+        }
+
+        let compatible = self
+            .selected_versions(name)
+            .find(|other| compat_group(other) == compat_group(version))
+            .cloned();
+
+        if let Some(selected) = compatible {
+            self.issue(ResolveIssue::Conflict {
+                package: package.to_string(),
+                dependency: name.to_string(),
+                req: dep.version_req.clone(),
+                selected,
+            });
+            return None;
+        }
+
+        Some((name.to_string(), version.clone()))
+    }
+}
+
+impl Display for ResolveIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveIssue::Unresolvable {
+                package,
+                dependency,
+                req,
+            } => write!(f, "{package}: no version of `{dependency}` matches `{req}`"),
+            ResolveIssue::Yanked {
+                package,
+                dependency,
+                version,
+            } => write!(
+                f,
+                "{package}: `{dependency}` only matches yanked versions, like {version}"
+            ),
+            ResolveIssue::Conflict {
+                package,
+                dependency,
+                req,
+                selected,
+            } => write!(
+                f,
+                "{package}: `{dependency}` `{req}` conflicts with {selected} used elsewhere"
+            ),
+            ResolveIssue::DuplicateMajor {
+                dependency,
+                versions,
+            } => {
+                let versions = versions.iter().map(Version::to_string).collect::<Vec<_>>();
+
+                write!(
+                    f,
+                    "`{dependency}` is used in several incompatible versions: {}",
+                    versions.join(", ")
+                )
+            }
+            ResolveIssue::OtherRegistry {
+                package,
+                dependency,
+                registry,
+            } => write!(
+                f,
+                "{package}: `{dependency}` comes from another registry, {registry}"
+            ),
+            ResolveIssue::UnknownFeature { package, feature } => {
+                write!(f, "{package}: no feature `{feature}`")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, vers: &str, yanked: bool, deps: Vec<api::CrateDep>) -> IndexEntry {
+        let features = [
+            ("default", vec!["json"]),
+            ("json", vec!["dep:serde_json"]),
+            ("extra", vec![]),
+        ];
+
+        IndexEntry {
+            yanked,
+            ..IndexEntry::for_test(api::CrateMeta {
+                deps,
+                features: features
+                    .into_iter()
+                    .map(|(name, enables)| {
+                        (name.into(), enables.into_iter().map(String::from).collect())
+                    })
+                    .collect(),
+                ..api::CrateMeta::for_test(name, vers)
+            })
+        }
+    }
+
+    fn dep(name: &str, version_req: &str) -> api::CrateDep {
+        api::CrateDep::for_test(name, version_req)
+    }
+
+    #[test]
+    fn reports_problems() {
+        let mut index = Index::default();
+
+        for entry in [
+            entry("serde_json", "1.0.0", false, vec![]),
+            entry("old", "0.1.0", true, vec![]),
+            entry("old", "0.2.0", false, vec![]),
+            entry("lib", "1.0.0", false, vec![dep("old", "^0.2")]),
+            entry(
+                "app",
+                "1.0.0",
+                false,
+                vec![
+                    api::CrateDep {
+                        optional: true,
+                        ..dep("serde_json", "^1")
+                    },
+                    api::CrateDep {
+                        features: vec!["extra".into()],
+                        ..dep("lib", "^1")
+                    },
+                    dep("old", "=0.1.0"),
+                    dep("missing", "^1"),
+                    api::CrateDep {
+                        kind: CrateDepKind::Dev,
+                        ..dep("test-only", "^1")
+                    },
+                    api::CrateDep {
+                        registry: Some("https://example.com".into()),
+                        ..dep("ext", "^1")
+                    },
+                ],
+            ),
+        ] {
+            index.add_crate_meta(entry);
+        }
+
+        let res = resolve(&index, "app", &Version::new(1, 0, 0), &[], true).unwrap();
+
+        let packages = res
+            .packages
+            .iter()
+            .map(|package| format!("{}@{}", package.name, package.version))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packages,
+            [
+                "app@1.0.0",
+                "lib@1.0.0",
+                "old@0.1.0",
+                "old@0.2.0",
+                "serde_json@1.0.0"
+            ]
+        );
+
+        let mut issues = res.issues.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+        issues.sort();
+        assert_eq!(
+            issues,
+            [
+                "`old` is used in several incompatible versions: 0.1.0, 0.2.0",
+                "app@1.0.0: `ext` comes from another registry, https://example.com",
+                "app@1.0.0: `old` only matches yanked versions, like 0.1.0",
+                "app@1.0.0: no version of `missing` matches `^1`",
+            ]
+        );
+
+        // Without default features the optional dependency isn't used.
+        let res = resolve(
+            &index,
+            "app",
+            &Version::new(1, 0, 0),
+            &["nope".into()],
+            false,
+        )
+        .unwrap();
+        assert!(!res.packages.iter().any(|p| p.name == "serde_json"));
+        assert!(res.issues.contains(&ResolveIssue::UnknownFeature {
+            package: "app@1.0.0".into(),
+            feature: "nope".into(),
+        }));
+    }
+}
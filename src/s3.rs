@@ -1,26 +1,27 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use crate::{
-    api::{self, CrateMeta},
-    index::IndexEntry,
-    store::CrateFile,
-};
+use async_trait::async_trait;
 use aws_sdk_s3::{
     Client,
     config::Credentials,
     operation::{
         get_object::builders::GetObjectFluentBuilder, put_object::builders::PutObjectFluentBuilder,
     },
+    presigning::PresigningConfig,
     primitives::ByteStream,
 };
 use bytes::Bytes;
+use futures_util::StreamExt;
+
+use crate::{
+    Error, Result,
+    error::Optional,
+    store::{ByteRange, ObjectStream, Store},
+};
 
 mod error;
 
 pub use error::S3Error;
-use semver::Version;
-
-pub type S3Result<T> = std::result::Result<T, S3Error>;
 
 #[derive(Clone)]
 pub struct S3Storage {
@@ -28,15 +29,6 @@ pub struct S3Storage {
     bucket_name: Arc<String>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-struct S3CrateMeta {
-    cksum: String,
-    meta: CrateMeta,
-    yanked: bool,
-}
-
-static CRATES_BUCKET_DIR: &str = "crates";
-
 impl S3Storage {
     pub async fn from_env() -> Self {
         let access_key =
@@ -77,150 +69,193 @@ impl S3Storage {
             .bucket(self.bucket_name.as_str())
             .key(key)
     }
+}
 
-    fn crate_path(&self, crate_name: &str, version: &Version) -> String {
-        format!("{CRATES_BUCKET_DIR}/{crate_name}/{version}/{crate_name}-{version}.crate")
+#[async_trait]
+impl Store for S3Storage {
+    async fn get_object(&self, key: &str) -> Result<Bytes> {
+        let res = self
+            .get(key)
+            .send()
+            .await
+            .map_err(S3Error::from)
+            .optional()?
+            .ok_or(Error::NotFound)?;
+
+        let data = res.body.collect().await.map_err(S3Error::from)?;
+
+        Ok(data.into_bytes())
     }
 
-    fn crate_meta_path(&self, crate_name: &str, version: &Version) -> String {
-        format!("crates/{crate_name}/{version}/{crate_name}-{version}.json")
+    async fn put_object(&self, key: &str, data: Bytes) -> Result<()> {
+        self.put(key)
+            .body(ByteStream::from(data))
+            .send()
+            .await
+            .map_err(S3Error::from)?;
+
+        Ok(())
     }
 
-    pub async fn load_index(&self) -> S3Result<crate::Index> {
-        let mut objects_paginator = self
-            .c
-            .list_objects_v2()
-            .bucket(self.bucket_name.as_str())
-            .into_paginator()
-            .page_size(50)
-            .send();
+    async fn put_object_if_absent(&self, key: &str, data: Bytes) -> Result<()> {
+        let res = self
+            .put(key)
+            .if_none_match("*")
+            .body(ByteStream::from(data))
+            .send()
+            .await;
 
-        let mut index = crate::Index::default();
-
-        while let Some(page) = objects_paginator.next().await.transpose()? {
-            for object in page.contents.into_iter().flatten() {
-                let Some(key) = object.key.as_deref() else {
-                    continue;
-                };
-
-                let mut split = key.split('/');
-
-                if Some(CRATES_BUCKET_DIR) != split.next() {
-                    continue;
-                }
-
-                let crate_name = split
-                    .next()
-                    .ok_or_else(|| S3Error::key_split(key, "finding crate name"))?;
-
-                let version = split
-                    .next()
-                    .ok_or_else(|| S3Error::key_split(key, "finding crate version"))?
-                    .parse::<Version>()
-                    .map_err(|_| S3Error::key_split(key, "invalid version"))?;
-
-                let filename = split
-                    .next()
-                    .ok_or_else(|| S3Error::key_split(key, "finding filename"))?;
-
-                // Only add entyr once per crate
-                if !filename.ends_with("json") {
-                    continue;
-                }
-
-                let meta_key = self.crate_meta_path(crate_name, &version);
-                let fetched_meta = self.get(meta_key).send().await?;
-
-                let bs = fetched_meta.body.collect().await.expect("collecting body");
-                let S3CrateMeta {
-                    cksum,
-                    meta,
-                    yanked,
-                } = serde_json::from_slice::<S3CrateMeta>(&bs.into_bytes())
-                    .expect("parsing meta file");
-
-                println!("adding {} {}", meta.name, meta.vers);
-                index.add_crate_meta(IndexEntry {
-                    cksum,
-                    meta,
-                    yanked,
-                })
-            }
-        }
+        match res.map_err(S3Error::from) {
+            Ok(_) => Ok(()),
+
+            // 412 when the object exists, 409 when a concurrent conditional
+            // write to the same key is in progress.
+            Err(S3Error::Non2xx {
+                status: Some(409 | 412),
+                ..
+            }) => Err(Error::Conflict),
 
-        Ok(index)
+            Err(err) => Err(err.into()),
+        }
     }
 
-    pub async fn download(
-        &self,
-        crate_name: impl AsRef<str>,
-        version: &Version,
-    ) -> S3Result<crate::CrateFile> {
-        let key = self.crate_path(crate_name.as_ref(), version);
-        let res = self.get(key).send().await?;
+    async fn get_object_tagged(&self, key: &str) -> Result<(Bytes, String)> {
+        let res = self
+            .get(key)
+            .send()
+            .await
+            .map_err(S3Error::from)
+            .optional()?
+            .ok_or(Error::NotFound)?;
 
-        let size = res.content_length().expect("missing content len") as usize;
-        let data = res.body.collect().await?;
+        let tag = res.e_tag().unwrap_or_default().to_string();
+        let data = res.body.collect().await.map_err(S3Error::from)?;
 
-        Ok(CrateFile {
-            size,
-            data: data.into_bytes(),
-        })
+        Ok((data.into_bytes(), tag))
     }
 
-    pub async fn list_objects(&self) -> S3Result<()> {
+    async fn put_object_if_match(&self, key: &str, data: Bytes, tag: &str) -> Result<()> {
         let res = self
-            .c
-            .list_objects_v2()
-            .bucket(self.bucket_name.as_str())
+            .put(key)
+            .if_match(tag)
+            .body(ByteStream::from(data))
             .send()
-            .await?;
+            .await;
 
-        if let Some(contents) = &res.contents {
-            for obj in contents {
-                let Some(key) = obj.key.as_deref() else {
-                    continue;
-                };
+        match res.map_err(S3Error::from) {
+            Ok(_) => Ok(()),
 
-                println!("Object: {key}")
-            }
+            // 412 when the object changed, 404 when it was removed, 409 when a
+            // concurrent conditional write to the same key is in progress.
+            Err(S3Error::Non2xx {
+                status: Some(404 | 409 | 412),
+                ..
+            }) => Err(Error::Conflict),
+
+            Err(err) => Err(err.into()),
         }
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        self.c
+            .delete_object()
+            .bucket(self.bucket_name.as_str())
+            .key(key)
+            .send()
+            .await
+            .map_err(S3Error::from)?;
 
         Ok(())
     }
 
-    pub async fn store_crate(&self, meta: api::CrateMeta, data: Bytes) -> S3Result<IndexEntry> {
-        let crate_name = &meta.name;
-        let crate_version = &meta.vers;
+    async fn get_object_stream(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectStream> {
+        let res = self
+            .get(key)
+            .set_range(range.map(|range| range.to_string()))
+            .send()
+            .await;
 
-        let crate_key = self.crate_path(&meta.name, crate_version);
-        let meta_key =
-            format!("crates/{crate_name}/{crate_version}/{crate_name}-{crate_version}.json");
+        let res = match res.map_err(S3Error::from) {
+            Ok(res) => res,
+            Err(S3Error::Non2xx {
+                status: Some(404), ..
+            }) => return Err(Error::NotFound),
+            Err(S3Error::Non2xx {
+                status: Some(416), ..
+            }) => return Err(Error::RangeNotSatisfiable),
+            Err(err) => return Err(err.into()),
+        };
 
-        let cksum = sha256::digest(data.as_ref());
+        let content_length = res
+            .content_length
+            .and_then(|len| u64::try_from(len).ok())
+            .ok_or_else(|| S3Error::StreamError(format!("no content length for `{key}`")))?;
 
-        let s3_entry = S3CrateMeta {
-            cksum,
-            meta,
-            yanked: false,
+        // Partial responses describe what they contain in `Content-Range`.
+        let (size, range) = match res.content_range.as_deref().and_then(parse_content_range) {
+            Some((first, last, size)) if range.is_some() => (size, Some((first, last))),
+            _ => (content_length, None),
         };
 
-        let json_vec = serde_json::to_vec(&s3_entry).expect("serializing crate meta");
+        let body = futures_util::stream::unfold(res.body, |mut body| async move {
+            let chunk = body.next().await?;
+            Some((chunk.map_err(std::io::Error::other), body))
+        });
 
-        self.put(crate_key)
-            .body(ByteStream::from(data))
-            .send()
-            .await?;
+        Ok(ObjectStream {
+            body: body.boxed(),
+            size,
+            range,
+        })
+    }
 
-        self.put(meta_key)
-            .body(ByteStream::from(json_vec))
-            .send()
-            .await?;
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<Option<String>> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .map_err(|err| S3Error::Presigning(err.to_string()))?;
 
-        Ok(IndexEntry {
-            cksum: s3_entry.cksum,
-            meta: s3_entry.meta,
-            yanked: s3_entry.yanked,
-        })
+        let req = self
+            .get(key)
+            .presigned(config)
+            .await
+            .map_err(S3Error::from)?;
+
+        Ok(Some(req.uri().to_string()))
+    }
+
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut objects_paginator = self
+            .c
+            .list_objects_v2()
+            .bucket(self.bucket_name.as_str())
+            .prefix(prefix)
+            .into_paginator()
+            .page_size(1000)
+            .send();
+
+        let mut keys = Vec::new();
+
+        while let Some(page) = objects_paginator
+            .next()
+            .await
+            .transpose()
+            .map_err(S3Error::from)?
+        {
+            keys.extend(
+                page.contents
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|object| object.key),
+            );
+        }
+
+        Ok(keys)
     }
 }
+
+/// Parses `bytes first-last/size`.
+fn parse_content_range(content_range: &str) -> Option<(u64, u64, u64)> {
+    let (range, size) = content_range.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+
+    Some((first.parse().ok()?, last.parse().ok()?, size.parse().ok()?))
+}
@@ -1,7 +1,8 @@
 use aws_sdk_s3::{
-    error::SdkError,
+    error::{DisplayErrorContext, SdkError},
     operation::{
-        get_object::GetObjectError, list_objects_v2::ListObjectsV2Error, put_object::PutObjectError,
+        delete_object::DeleteObjectError, get_object::GetObjectError,
+        list_objects_v2::ListObjectsV2Error, put_object::PutObjectError,
     },
 };
 
@@ -13,20 +14,11 @@ pub enum S3Error {
         message: String,
     },
 
-    #[error("splitting key `{key}`: {message}")]
-    KeySplit { key: String, message: String },
-
     #[error("Streaming data error: {0}")]
     StreamError(String),
-}
 
-impl S3Error {
-    pub fn key_split(key: impl Into<String>, message: impl Into<String>) -> Self {
-        Self::KeySplit {
-            key: key.into(),
-            message: message.into(),
-        }
-    }
+    #[error("Presigning: {0}")]
+    Presigning(String),
 }
 
 impl<T> crate::error::Optional<T, S3Error> for Result<T, S3Error> {
@@ -50,15 +42,11 @@ impl From<aws_sdk_s3::primitives::ByteStreamError> for S3Error {
 
 impl From<SdkError<PutObjectError>> for S3Error {
     fn from(err: SdkError<PutObjectError>) -> Self {
-        println!("{err:#?}");
-
         let message = err
             .as_service_error()
             .and_then(|err| err.meta().message())
-            .unwrap_or("-")
-            .to_string();
-
-        println!("message: {message}",);
+            .map(String::from)
+            .unwrap_or_else(|| DisplayErrorContext(&err).to_string());
 
         Self::Non2xx {
             status: err.raw_response().map(|r| r.status().as_u16()),
@@ -69,15 +57,11 @@ impl From<SdkError<PutObjectError>> for S3Error {
 
 impl From<SdkError<ListObjectsV2Error>> for S3Error {
     fn from(err: SdkError<ListObjectsV2Error>) -> Self {
-        println!("{err:#?}");
-
         let message = err
             .as_service_error()
             .and_then(|err| err.meta().message())
-            .unwrap_or("-")
-            .to_string();
-
-        println!("message: {message}",);
+            .map(String::from)
+            .unwrap_or_else(|| DisplayErrorContext(&err).to_string());
 
         Self::Non2xx {
             status: err.raw_response().map(|r| r.status().as_u16()),
@@ -88,15 +72,26 @@ impl From<SdkError<ListObjectsV2Error>> for S3Error {
 
 impl From<SdkError<GetObjectError>> for S3Error {
     fn from(err: SdkError<GetObjectError>) -> Self {
-        println!("{err:#?}");
-
         let message = err
             .as_service_error()
             .and_then(|err| err.meta().message())
-            .unwrap_or("-")
-            .to_string();
+            .map(String::from)
+            .unwrap_or_else(|| DisplayErrorContext(&err).to_string());
 
-        println!("message: {message}",);
+        Self::Non2xx {
+            status: err.raw_response().map(|r| r.status().as_u16()),
+            message,
+        }
+    }
+}
+
+impl From<SdkError<DeleteObjectError>> for S3Error {
+    fn from(err: SdkError<DeleteObjectError>) -> Self {
+        let message = err
+            .as_service_error()
+            .and_then(|err| err.meta().message())
+            .map(String::from)
+            .unwrap_or_else(|| DisplayErrorContext(&err).to_string());
 
         Self::Non2xx {
             status: err.raw_response().map(|r| r.status().as_u16()),
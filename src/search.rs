@@ -0,0 +1,185 @@
+//! Full text search over crate names, descriptions, keywords and categories.
+//!
+//! Text is split into lowercase alphanumeric tokens, so matching ignores case
+//! and whether words are joined by `-` or `_`. Every query token has to match
+//! a token of the crate, or be a prefix of one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{api, index::canonical_crate_name};
+
+/// How much a match in each field counts towards the relevance of a crate.
+const NAME_WEIGHT: u32 = 10;
+const KEYWORD_WEIGHT: u32 = 5;
+const CATEGORY_WEIGHT: u32 = 3;
+const DESCRIPTION_WEIGHT: u32 = 1;
+
+/// Bonus for crates named exactly like the query, so they always come first.
+const EXACT_NAME_BONUS: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sort {
+    #[default]
+    Relevance,
+    Alpha,
+    Downloads,
+    #[serde(alias = "recent")]
+    RecentUpdates,
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    /// Maps tokens to the crates containing them, with the weight of the
+    /// best field they are in.
+    postings: BTreeMap<String, HashMap<String, u32>>,
+
+    /// The tokens of every crate, to remove them again when it changes.
+    tokens: HashMap<String, Vec<String>>,
+}
+
+impl SearchIndex {
+    /// Replaces what is indexed for a crate with the metadata of `meta`,
+    /// usually its newest version.
+    pub fn update(&mut self, meta: &api::CrateMeta) {
+        self.remove(&meta.name);
+
+        let mut weights = HashMap::<String, u32>::new();
+
+        let fields = [
+            (NAME_WEIGHT, vec![meta.name.as_str()]),
+            (
+                KEYWORD_WEIGHT,
+                meta.keywords.iter().map(String::as_str).collect(),
+            ),
+            (
+                CATEGORY_WEIGHT,
+                meta.categories.iter().map(String::as_str).collect(),
+            ),
+            (
+                DESCRIPTION_WEIGHT,
+                meta.description.as_deref().into_iter().collect(),
+            ),
+        ];
+
+        for (weight, texts) in fields {
+            for token in texts.into_iter().flat_map(tokenize) {
+                let best = weights.entry(token).or_default();
+                *best = (*best).max(weight);
+            }
+        }
+
+        for (token, weight) in &weights {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .insert(meta.name.clone(), *weight);
+        }
+
+        self.tokens
+            .insert(meta.name.clone(), weights.into_keys().collect());
+    }
+
+    fn remove(&mut self, crate_name: &str) {
+        for token in self.tokens.remove(crate_name).unwrap_or_default() {
+            if let Some(crates) = self.postings.get_mut(&token) {
+                crates.remove(crate_name);
+
+                if crates.is_empty() {
+                    self.postings.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Finds the crates matching every token of `query`, with their
+    /// relevance. An empty query matches all crates.
+    pub fn search(&self, query: &str) -> HashMap<&str, u32> {
+        let terms = tokenize(query).collect::<Vec<_>>();
+
+        if terms.is_empty() {
+            return self.tokens.keys().map(|name| (name.as_str(), 0)).collect();
+        }
+
+        let mut scores: Option<HashMap<&str, u32>> = None;
+
+        for term in &terms {
+            let mut term_scores = HashMap::<&str, u32>::new();
+
+            let matches = self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(token, _)| token.starts_with(term.as_str()));
+
+            for (token, crates) in matches {
+                // Whole words count double compared to prefixes.
+                let factor = if token == term { 2 } else { 1 };
+
+                for (name, weight) in crates {
+                    let score = term_scores.entry(name.as_str()).or_default();
+                    *score = (*score).max(weight * factor);
+                }
+            }
+
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(name, score)| Some((name, score + term_scores.get(name)?)))
+                    .collect(),
+            });
+        }
+
+        let mut scores = scores.unwrap_or_default();
+        let canonical_query = canonical_crate_name(query.trim());
+
+        for (name, score) in &mut scores {
+            if canonical_crate_name(name) == canonical_query {
+                *score += EXACT_NAME_BONUS;
+            }
+        }
+
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(name: &str, description: &str, keywords: &[&str]) -> api::CrateMeta {
+        api::CrateMeta {
+            description: Some(description.to_string()),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            ..api::CrateMeta::for_test(name, "0.1.0")
+        }
+    }
+
+    #[test]
+    fn ranks_names_over_descriptions() {
+        let mut index = SearchIndex::default();
+
+        index.update(&meta("serde_json", "JSON support for Serde", &["json"]));
+        index.update(&meta("json-pointer", "Pointers into JSON", &[]));
+        index.update(&meta("config", "Reads settings, e.g. from json files", &[]));
+
+        let scores = index.search("JSON");
+        assert_eq!(scores.len(), 3);
+        assert!(scores["json-pointer"] > scores["config"]);
+
+        let scores = index.search("serde-json");
+        assert_eq!(scores.keys().collect::<Vec<_>>(), [&"serde_json"]);
+        assert!(scores["serde_json"] > EXACT_NAME_BONUS);
+
+        // Prefixes match, and changed metadata replaces the old tokens.
+        assert_eq!(index.search("poin").len(), 1);
+        index.update(&meta("json-pointer", "Paths into JSON", &[]));
+        assert!(index.search("pointers").is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{policy::Policy, tarball, validate};
+
+/// The longest a presigned S3 URL can be valid, a week.
+const MAX_PRESIGNED_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Deployment specific settings, read from the environment at startup.
+#[derive(Debug)]
+pub struct Settings {
+    /// Only needed to serve, commands like `export-git-index` do without.
+    pub listen_addr: Option<String>,
+
+    /// Where the whole router is mounted, e.g. `/registry`. Empty when it is
+    /// served from the root.
+    pub path_prefix: String,
+
+    /// The externally visible base URL, advertised as `api` in `config.json`.
+    pub public_url: String,
+
+    /// The `dl` template of `config.json`. May point at a CDN or directly at
+    /// the bucket, using cargo's `{crate}`, `{version}`, `{prefix}`,
+    /// `{lowerprefix}` and `{sha256-checksum}` markers.
+    pub dl_template: String,
+
+    /// The name the `Cargo.toml` snippets of the web UI use for the registry,
+    /// as in `[registries.<name>]`.
+    pub registry_name: String,
+
+    /// Requires a valid API token for index reads and downloads as well,
+    /// advertised to cargo as `auth-required` in `config.json`.
+    pub auth_required: bool,
+
+    /// Index URLs of other registries that dependencies may come from.
+    pub allowed_registries: Vec<String>,
+
+    /// Accepts versions with build metadata such as `1.0.0+build.5`.
+    pub allow_build_metadata: bool,
+
+    pub crate_limits: tarball::Limits,
+
+    /// How often to poll the store for changes made by other replicas.
+    /// `None` when running as the only instance.
+    pub sync_interval: Option<Duration>,
+
+    /// A sparse registry, usually `https://index.crates.io`, whose crates are
+    /// proxied and cached for crates that aren't published here.
+    pub upstream_index: Option<String>,
+
+    /// Accepts new crates named like an upstream crate, which then replace
+    /// the upstream crate for every client.
+    pub allow_shadowing_upstream: bool,
+
+    /// Answers downloads with a redirect to a presigned URL valid for this
+    /// long, instead of streaming the crate through lagret. `None` to stream.
+    pub presigned_downloads: Option<Duration>,
+
+    /// How often download counts are added to the counts in the store.
+    /// `None` to only add them on shutdown.
+    pub downloads_flush_interval: Option<Duration>,
+
+    /// Where to keep a git repository copy of the index, for clients that
+    /// only support the git protocol.
+    pub git_index_dir: Option<PathBuf>,
+
+    /// Extra rules for published crates, read from `LAGRET_POLICY_FILE`.
+    /// Allows everything when unset.
+    pub policy: Policy,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        let listen_addr = std::env::var("LAGRET_LISTEN_ADDR").ok();
+
+        let path_prefix =
+            normalize_path_prefix(&std::env::var("LAGRET_PATH_PREFIX").unwrap_or_default());
+
+        let public_url = std::env::var("LAGRET_PUBLIC_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| {
+                let listen_addr = listen_addr
+                    .as_deref()
+                    .expect("env var LAGRET_PUBLIC_URL or LAGRET_LISTEN_ADDR");
+
+                format!("http://{listen_addr}{path_prefix}")
+            });
+
+        let dl_template = std::env::var("LAGRET_DL_TEMPLATE")
+            .unwrap_or_else(|_| format!("{public_url}/{{crate}}/{{version}}/download"));
+
+        let allowed_registries = match std::env::var("LAGRET_ALLOWED_REGISTRIES") {
+            Ok(list) => list
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(String::from)
+                .collect(),
+
+            Err(_) => validate::CRATES_IO_INDEX
+                .iter()
+                .map(|url| url.to_string())
+                .collect(),
+        };
+
+        Self {
+            listen_addr,
+            path_prefix,
+            public_url,
+            dl_template,
+            registry_name: std::env::var("LAGRET_REGISTRY_NAME")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "lagret".to_string()),
+            auth_required: env_flag("LAGRET_AUTH_REQUIRED"),
+            allowed_registries,
+            allow_build_metadata: env_flag("LAGRET_ALLOW_BUILD_METADATA"),
+            crate_limits: tarball::Limits {
+                max_crate_size: env_parse("LAGRET_MAX_CRATE_SIZE", 10 << 20),
+                max_unpacked_size: env_parse("LAGRET_MAX_UNPACKED_SIZE", 512 << 20),
+            },
+            sync_interval: match env_parse("LAGRET_SYNC_INTERVAL_SECS", 5) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            upstream_index: std::env::var("LAGRET_UPSTREAM_INDEX")
+                .ok()
+                .filter(|url| !url.is_empty()),
+            allow_shadowing_upstream: env_flag("LAGRET_ALLOW_SHADOWING_UPSTREAM"),
+            presigned_downloads: match env_parse("LAGRET_PRESIGNED_DOWNLOADS_SECS", 0) {
+                0 => None,
+                secs if secs > MAX_PRESIGNED_SECS => panic!(
+                    "invalid LAGRET_PRESIGNED_DOWNLOADS_SECS `{secs}`, at most {MAX_PRESIGNED_SECS}"
+                ),
+                secs => Some(Duration::from_secs(secs)),
+            },
+            downloads_flush_interval: match env_parse("LAGRET_DOWNLOADS_FLUSH_SECS", 60) {
+                0 => None,
+                secs => Some(Duration::from_secs(secs)),
+            },
+            git_index_dir: std::env::var_os("LAGRET_GIT_INDEX_DIR").map(PathBuf::from),
+            policy: std::env::var("LAGRET_POLICY_FILE")
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(|path| Policy::load(&path))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Turns `registry/`, `/registry` and `/registry/` into `/registry`.
+fn normalize_path_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_matches('/');
+
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{trimmed}")
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name).as_deref() {
+        Ok("1" | "true" | "yes") => true,
+        Ok("0" | "false" | "no" | "") | Err(_) => false,
+        Ok(other) => panic!("invalid {name} `{other}`, expected `true` or `false`"),
+    }
+}
+
+fn env_parse<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("invalid {name} `{value}`")),
+        Err(_) => default,
+    }
+}
@@ -1,9 +1,470 @@
-//! This mod is meant to contain the Store trait.
-//! However, the only storage as of now is the S3 one.
+//! The storage abstraction used by lagret.
+//!
+//! A [`Store`] is a flat object store addressed by `/` separated keys. The
+//! backends only implement the object primitives, the crate specific
+//! operations are built on top of those and share a single key layout.
 
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
 use bytes::Bytes;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use futures_util::stream::BoxStream;
+use semver::Version;
+
+use crate::{
+    Error, Result, api,
+    auth::Token,
+    error::Optional,
+    index::{self, Index, IndexEntry},
+    local::LocalStorage,
+    owners::Owners,
+    s3::S3Storage,
+};
+
+/// A streamed object body.
+pub type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+pub struct ObjectStream {
+    pub body: ByteStream,
+
+    /// The size of the whole object.
+    pub size: u64,
+
+    /// The first and last byte in `body`, when only a range was requested.
+    pub range: Option<(u64, u64)>,
+}
+
+/// A single range from a `Range: bytes=...` header. Only backends know the
+/// object size, so they resolve it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=first-last`, inclusive.
+    FromTo(u64, u64),
+
+    /// `bytes=first-`
+    From(u64),
+
+    /// `bytes=-n`, the last `n` bytes.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// Parses a `Range` header. Multiple ranges aren't supported, so those
+    /// are ignored and the whole object is served instead.
+    pub fn parse(header: &str) -> Option<Self> {
+        let spec = header.trim().strip_prefix("bytes=")?;
+
+        if spec.contains(',') {
+            return None;
+        }
+
+        let (first, last) = spec.split_once('-')?;
+
+        match (first.trim(), last.trim()) {
+            ("", n) => Some(Self::Last(n.parse().ok()?)),
+            (first, "") => Some(Self::From(first.parse().ok()?)),
+            (first, last) => {
+                let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+                (first <= last).then_some(Self::FromTo(first, last))
+            }
+        }
+    }
+
+    /// The first and last byte of an object of `size` bytes to serve, or
+    /// [`Error::RangeNotSatisfiable`].
+    pub fn resolve(self, size: u64) -> Result<(u64, u64)> {
+        let range = match self {
+            Self::FromTo(first, last) => (first, last.min(size.saturating_sub(1))),
+            Self::From(first) => (first, size.saturating_sub(1)),
+            Self::Last(n) => (size.saturating_sub(n), size.saturating_sub(1)),
+        };
+
+        if size == 0 || range.0 >= size || matches!(self, Self::Last(0)) {
+            return Err(Error::RangeNotSatisfiable);
+        }
+
+        Ok(range)
+    }
+}
+
+impl std::fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FromTo(first, last) => write!(f, "bytes={first}-{last}"),
+            Self::From(first) => write!(f, "bytes={first}-"),
+            Self::Last(n) => write!(f, "bytes=-{n}"),
+        }
+    }
+}
+
+static CRATES_DIR: &str = "crates";
+static TOKENS_DIR: &str = "tokens";
+
+/// All index entries in one gzipped JSON object, rewritten on every change.
+static INDEX_SNAPSHOT_PATH: &str = "index/snapshot.json.gz";
+
+pub fn crate_path(crate_name: &str, version: &Version) -> String {
+    format!("{CRATES_DIR}/{crate_name}/{version}/{crate_name}-{version}.crate")
+}
+
+pub fn crate_meta_path(crate_name: &str, version: &Version) -> String {
+    format!("{CRATES_DIR}/{crate_name}/{version}/{crate_name}-{version}.json")
+}
+
+pub fn readme_path(crate_name: &str, version: &Version) -> String {
+    format!("{CRATES_DIR}/{crate_name}/{version}/{crate_name}-{version}.readme")
+}
+
+fn owners_path(crate_name: &str) -> String {
+    format!("{CRATES_DIR}/{crate_name}/owners.json")
+}
+
+fn token_path(token_hash: &str) -> String {
+    format!("{TOKENS_DIR}/{token_hash}.json")
+}
+
+#[derive(serde::Serialize)]
+struct IndexSnapshotRef<'a> {
+    generation: u64,
+    entries: Vec<&'a IndexEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct IndexSnapshot {
+    #[serde(default)]
+    generation: u64,
+    entries: Vec<IndexEntry>,
+}
+
+#[derive(Clone)]
+pub struct StoreState(pub Arc<dyn Store>);
+
+/// Picks the backend from `LAGRET_STORE`, which is either `s3` (the default)
+/// or `local`.
+pub async fn from_env() -> Arc<dyn Store> {
+    match std::env::var("LAGRET_STORE").as_deref() {
+        Ok("s3") | Err(_) => Arc::new(S3Storage::from_env().await),
+        Ok("local") => Arc::new(LocalStorage::from_env()),
+        Ok(other) => panic!("unknown LAGRET_STORE `{other}`, expected `s3` or `local`"),
+    }
+}
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    /// Fetches the object stored under `key`, or [`Error::NotFound`].
+    async fn get_object(&self, key: &str) -> Result<Bytes>;
+
+    async fn put_object(&self, key: &str, data: Bytes) -> Result<()>;
+
+    /// Like [`Store::put_object`], but fails with [`Error::Conflict`]
+    /// if the object exists already. The check and the write are atomic.
+    async fn put_object_if_absent(&self, key: &str, data: Bytes) -> Result<()>;
+
+    /// Like [`Store::get_object`], but also returns a tag that changes
+    /// whenever the object is overwritten, for [`Store::put_object_if_match`].
+    async fn get_object_tagged(&self, key: &str) -> Result<(Bytes, String)>;
+
+    /// Overwrites the object under `key` if it still has `tag`, or fails with
+    /// [`Error::Conflict`]. The check and the write are atomic.
+    async fn put_object_if_match(&self, key: &str, data: Bytes, tag: &str) -> Result<()>;
+
+    /// Removes the object under `key`. Removing a missing object is not an error.
+    async fn delete_object(&self, key: &str) -> Result<()>;
+
+    /// Streams the object stored under `key`, or just `range` of it.
+    async fn get_object_stream(&self, key: &str, range: Option<ByteRange>) -> Result<ObjectStream>;
+
+    /// Lists the keys of all objects starting with `prefix`.
+    async fn list_keys(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// A URL anyone can download the object under `key` from for `expires_in`,
+    /// or `None` when the backend can't hand out such URLs.
+    async fn presigned_url(&self, _key: &str, _expires_in: Duration) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn download(
+        &self,
+        crate_name: &str,
+        version: &Version,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectStream> {
+        self.get_object_stream(&crate_path(crate_name, version), range)
+            .await
+    }
+
+    /// Stores a new crate version. The metadata object is what makes a
+    /// version published, so it is only written once the `.crate` file is in
+    /// place, and both writes fail instead of overwriting existing objects.
+    async fn store_crate(&self, meta: api::CrateMeta, data: Bytes) -> Result<IndexEntry> {
+        let crate_key = crate_path(&meta.name, &meta.vers);
+        let meta_key = crate_meta_path(&meta.name, &meta.vers);
+
+        let entry = IndexEntry {
+            cksum: sha256::digest(data.as_ref()),
+            meta,
+            yanked: false,
+            updated_at: Some(index::unix_now()),
+        };
+
+        let crate_exists = || Error::CrateExists {
+            name: entry.meta.name.clone(),
+            version: entry.meta.vers.clone(),
+        };
+
+        let created_crate_file = match self.put_object_if_absent(&crate_key, data.clone()).await {
+            Ok(()) => true,
+
+            // Either a concurrent publish of the same version, or one that
+            // failed before writing its metadata. Identical bytes can be
+            // shared, and the metadata write below picks the winner.
+            Err(Error::Conflict) => {
+                if self.get_object(&meta_key).await.optional()?.is_some() {
+                    return Err(crate_exists());
+                }
+
+                if self.get_object(&crate_key).await.optional()? != Some(data) {
+                    return Err(Error::Conflict);
+                }
+
+                false
+            }
+
+            Err(err) => return Err(err),
+        };
+
+        let json_vec = serde_json::to_vec(&entry)?;
+
+        match self
+            .put_object_if_absent(&meta_key, Bytes::from(json_vec))
+            .await
+        {
+            Ok(()) => Ok(entry),
+            Err(Error::Conflict) => Err(crate_exists()),
+
+            Err(err) => {
+                if created_crate_file && let Err(err) = self.delete_object(&crate_key).await {
+                    eprintln!("removing `{crate_key}` after failed publish: {err}");
+                }
+
+                Err(err)
+            }
+        }
+    }
+
+    async fn load_readme(&self, crate_name: &str, version: &Version) -> Result<String> {
+        let data = self.get_object(&readme_path(crate_name, version)).await?;
+
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    async fn store_readme(
+        &self,
+        crate_name: &str,
+        version: &Version,
+        readme: String,
+    ) -> Result<()> {
+        self.put_object(&readme_path(crate_name, version), Bytes::from(readme))
+            .await
+    }
+
+    /// Removes a stored version again, metadata first so that it is never
+    /// left without its `.crate` file.
+    async fn remove_crate(&self, crate_name: &str, version: &Version) -> Result<()> {
+        self.delete_object(&crate_meta_path(crate_name, version))
+            .await?;
+        self.delete_object(&crate_path(crate_name, version)).await
+    }
+
+    /// Removes `.crate` files without metadata, left behind by publishes that
+    /// were interrupted. Must not run while publishes are in progress.
+    async fn remove_orphans(&self) -> Result<()> {
+        let keys = self.list_keys(&format!("{CRATES_DIR}/")).await?;
+
+        for key in &keys {
+            let Some(stem) = key.strip_suffix(".crate") else {
+                continue;
+            };
+
+            if !keys.contains(&format!("{stem}.json")) {
+                println!("removing orphan {key}");
+                self.delete_object(key).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the metadata object of an already stored crate version.
+    async fn update_crate_meta(&self, entry: &IndexEntry) -> Result<()> {
+        let meta_key = crate_meta_path(&entry.meta.name, &entry.meta.vers);
+        let json_vec = serde_json::to_vec(entry)?;
+
+        self.put_object(&meta_key, Bytes::from(json_vec)).await
+    }
+
+    /// Loads the index snapshot, falling back to a full rebuild when there is
+    /// none yet.
+    async fn load_index(&self) -> Result<Index> {
+        match self.get_object(INDEX_SNAPSHOT_PATH).await {
+            Ok(data) => {
+                let mut json = Vec::new();
+                GzDecoder::new(data.as_ref()).read_to_end(&mut json)?;
+
+                let snapshot = serde_json::from_slice::<IndexSnapshot>(&json)?;
+                let mut index = Index::default();
+
+                for entry in snapshot.entries {
+                    index.add_crate_meta(entry);
+                }
+
+                index.generation = snapshot.generation;
+
+                Ok(index)
+            }
+
+            Err(Error::NotFound) => {
+                println!("no index snapshot, rebuilding");
+
+                let index = self.rebuild_index().await?;
+                self.store_index_snapshot(&index).await?;
+
+                Ok(index)
+            }
+
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn store_index_snapshot(&self, index: &Index) -> Result<()> {
+        let snapshot = IndexSnapshotRef {
+            generation: index.generation,
+            entries: index.entries().collect(),
+        };
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut gz, &snapshot)?;
+        gz.flush()?;
+
+        self.put_object(INDEX_SNAPSHOT_PATH, Bytes::from(gz.finish()?))
+            .await
+    }
+
+    /// Builds the index from the metadata objects of every stored version.
+    /// This is slow, and only needed when the snapshot is lost or damaged.
+    async fn rebuild_index(&self) -> Result<Index> {
+        let mut index = Index::default();
+
+        for key in self.list_keys(&format!("{CRATES_DIR}/")).await? {
+            // Only `crates/{name}/{version}/{name}-{version}.json`, once per version
+            if key.split('/').count() != 4 || !key.ends_with(".json") {
+                continue;
+            }
+
+            let entry = serde_json::from_slice::<IndexEntry>(&self.get_object(&key).await?)?;
+
+            println!("adding {} {}", entry.meta.name, entry.meta.vers);
+            index.add_crate_meta(entry);
+        }
+
+        Ok(index)
+    }
+
+    async fn load_owners(&self, crate_name: &str) -> Result<Owners> {
+        let data = self.get_object(&owners_path(crate_name)).await?;
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Records the first owners of a new crate, or fails with
+    /// [`Error::Conflict`] if it has owners already.
+    async fn create_owners(&self, crate_name: &str, owners: &Owners) -> Result<()> {
+        let json_vec = serde_json::to_vec(owners)?;
+
+        self.put_object_if_absent(&owners_path(crate_name), Bytes::from(json_vec))
+            .await
+    }
+
+    async fn delete_owners(&self, crate_name: &str) -> Result<()> {
+        self.delete_object(&owners_path(crate_name)).await
+    }
+
+    /// Loads the owners together with a tag for [`Store::replace_owners`].
+    async fn load_owners_tagged(&self, crate_name: &str) -> Result<(Owners, String)> {
+        let (data, tag) = self.get_object_tagged(&owners_path(crate_name)).await?;
+
+        Ok((serde_json::from_slice(&data)?, tag))
+    }
+
+    /// Replaces owners loaded with [`Store::load_owners_tagged`], or fails
+    /// with [`Error::Conflict`] if they were changed since.
+    async fn replace_owners(&self, crate_name: &str, owners: &Owners, tag: &str) -> Result<()> {
+        let json_vec = serde_json::to_vec(owners)?;
+
+        self.put_object_if_match(&owners_path(crate_name), Bytes::from(json_vec), tag)
+            .await
+    }
+
+    async fn load_token(&self, token_hash: &str) -> Result<Token> {
+        let data = self.get_object(&token_path(token_hash)).await?;
+
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    async fn store_token(&self, token_hash: &str, token: &Token) -> Result<()> {
+        let json_vec = serde_json::to_vec(token)?;
+
+        self.put_object(&token_path(token_hash), Bytes::from(json_vec))
+            .await
+    }
+
+    async fn delete_token(&self, token_hash: &str) -> Result<()> {
+        self.delete_object(&token_path(token_hash)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_range_parse() {
+        assert_eq!(
+            ByteRange::parse("bytes=0-99"),
+            Some(ByteRange::FromTo(0, 99))
+        );
+        assert_eq!(ByteRange::parse("bytes=100-"), Some(ByteRange::From(100)));
+        assert_eq!(ByteRange::parse("bytes=-100"), Some(ByteRange::Last(100)));
+        assert_eq!(
+            ByteRange::parse(" bytes= 5 - 9 "),
+            Some(ByteRange::FromTo(5, 9))
+        );
+
+        // Malformed, or several ranges, serve the whole object.
+        for header in [
+            "bytes=9-5",
+            "bytes=-",
+            "bytes=a-b",
+            "bytes=5",
+            "items=0-9",
+            "bytes=0-9,20-29",
+        ] {
+            assert_eq!(ByteRange::parse(header), None, "{header}");
+        }
+    }
 
-pub struct CrateFile {
-    pub data: Bytes,
-    pub size: usize,
+    #[test]
+    fn byte_range_resolve() {
+        assert_eq!(ByteRange::FromTo(0, 999).resolve(100).unwrap(), (0, 99));
+        assert_eq!(ByteRange::Last(10).resolve(100).unwrap(), (90, 99));
+        assert!(matches!(
+            ByteRange::From(100).resolve(100),
+            Err(Error::RangeNotSatisfiable)
+        ));
+    }
 }
@@ -0,0 +1,234 @@
+//! Inspection of uploaded `.crate` files before they are stored.
+
+use std::{
+    io::{self, Read},
+    path::{Component, Path},
+};
+
+use flate2::read::GzDecoder;
+use semver::Version;
+
+use crate::{Error, Result, api};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Maximum size of the `.crate` file itself.
+    pub max_crate_size: u64,
+
+    /// Maximum size of the decompressed tarball.
+    pub max_unpacked_size: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct Manifest {
+    package: ManifestPackage,
+}
+
+#[derive(serde::Deserialize)]
+struct ManifestPackage {
+    name: String,
+    version: Version,
+}
+
+/// Fails reads once more than `remaining` bytes went through.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+    max: u64,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        self.remaining = self.remaining.checked_sub(n as u64).ok_or_else(|| {
+            io::Error::other(format!("unpacked crate is larger than {} bytes", self.max))
+        })?;
+
+        Ok(n)
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> Error {
+    Error::BadRequest(format!("invalid crate file: {message}"))
+}
+
+/// Checks that `data` is a gzipped tarball with everything below
+/// `{name}-{version}/`, and that its `Cargo.toml` describes the crate in
+/// `meta`. Returns the README named by `meta.readme_file`, if it is there.
+pub fn verify(meta: &api::CrateMeta, data: &[u8], limits: Limits) -> Result<Option<String>> {
+    if data.len() as u64 > limits.max_crate_size {
+        return Err(Error::BadRequest(format!(
+            "crate file is {} bytes, the maximum is {}",
+            data.len(),
+            limits.max_crate_size
+        )));
+    }
+
+    let prefix = format!("{}-{}", meta.name, meta.vers);
+    let manifest_path = Path::new(&prefix).join("Cargo.toml");
+
+    // Cargo copies READMEs from outside the package next to `Cargo.toml`.
+    let readme_paths = meta
+        .readme_file
+        .as_deref()
+        .map(Path::new)
+        .into_iter()
+        .flat_map(|path| [Some(path), path.file_name().map(Path::new)])
+        .flatten()
+        .map(|path| Path::new(&prefix).join(path))
+        .collect::<Vec<_>>();
+
+    let unpacked = LimitedReader {
+        inner: GzDecoder::new(data),
+        remaining: limits.max_unpacked_size,
+        max: limits.max_unpacked_size,
+    };
+    let mut archive = tar::Archive::new(unpacked);
+
+    let mut manifest = None;
+    let mut readme = None;
+
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let path = entry.path().map_err(invalid)?.into_owned();
+
+        let mut components = path.components();
+
+        if components.next() != Some(Component::Normal(prefix.as_ref())) {
+            return Err(invalid(format!(
+                "`{}` is outside of `{prefix}/`",
+                path.display()
+            )));
+        }
+
+        if components.any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return Err(invalid(format!("invalid path `{}`", path.display())));
+        }
+
+        let entry_type = entry.header().entry_type();
+
+        if !(entry_type.is_file() || entry_type.is_dir() || entry_type.is_pax_global_extensions()) {
+            return Err(invalid(format!(
+                "`{}` is a link or special file",
+                path.display()
+            )));
+        }
+
+        if path == manifest_path {
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).map_err(invalid)?;
+            manifest = Some(contents);
+        } else if readme.is_none() && readme_paths.contains(&path) {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).map_err(invalid)?;
+            readme = Some(String::from_utf8_lossy(&contents).into_owned());
+        } else {
+            // Drain the entry so the whole archive counts against the limit.
+            io::copy(&mut entry, &mut io::sink()).map_err(invalid)?;
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| invalid(format!("missing `{prefix}/Cargo.toml`")))?;
+    let Manifest { package } = toml::from_str::<Manifest>(&manifest)
+        .map_err(|err| invalid(format!("parsing `Cargo.toml`: {err}")))?;
+
+    if package.name != meta.name || package.version != meta.vers {
+        return Err(invalid(format!(
+            "`Cargo.toml` describes `{}-{}`, but `{prefix}` was published",
+            package.name, package.version
+        )));
+    }
+
+    Ok(readme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::{Compression, write::GzEncoder};
+
+    const LIMITS: Limits = Limits {
+        max_crate_size: 1 << 20,
+        max_unpacked_size: 1 << 20,
+    };
+
+    fn tarball(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            // Set the raw name, `set_path` refuses the malicious ones.
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            builder.append(&header, *data).expect("appending");
+        }
+
+        builder
+            .into_inner()
+            .and_then(|gz| gz.finish())
+            .expect("finishing")
+    }
+
+    const MANIFEST: &[u8] = b"[package]\nname = \"foo\"\nversion = \"0.1.0\"\n";
+
+    #[test]
+    fn accepts_matching_tarball() {
+        let data = tarball(&[
+            ("foo-0.1.0/Cargo.toml", MANIFEST),
+            ("foo-0.1.0/src/lib.rs", b""),
+            ("foo-0.1.0/README.md", b"# foo"),
+        ]);
+
+        let mut meta = api::CrateMeta::for_test("foo", "0.1.0");
+        assert_eq!(verify(&meta, &data, LIMITS).expect("valid crate"), None);
+
+        meta.readme_file = Some("../README.md".into());
+        let readme = verify(&meta, &data, LIMITS).expect("valid crate");
+        assert_eq!(readme.as_deref(), Some("# foo"));
+    }
+
+    #[test]
+    fn rejects_mismatch_and_escapes() {
+        let data = tarball(&[("foo-0.1.0/Cargo.toml", MANIFEST)]);
+        assert!(verify(&api::CrateMeta::for_test("foo", "0.2.0"), &data, LIMITS).is_err());
+
+        let data = tarball(&[("bar-0.1.0/Cargo.toml", MANIFEST)]);
+        assert!(verify(&api::CrateMeta::for_test("bar", "0.1.0"), &data, LIMITS).is_err());
+
+        let data = tarball(&[
+            ("foo-0.1.0/Cargo.toml", MANIFEST),
+            ("foo-0.1.0/../evil", b""),
+        ]);
+        assert!(verify(&api::CrateMeta::for_test("foo", "0.1.0"), &data, LIMITS).is_err());
+
+        let data = tarball(&[("foo-0.1.0/src/lib.rs", b"")]);
+        assert!(verify(&api::CrateMeta::for_test("foo", "0.1.0"), &data, LIMITS).is_err());
+
+        assert!(
+            verify(
+                &api::CrateMeta::for_test("foo", "0.1.0"),
+                b"garbage",
+                LIMITS
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn enforces_unpacked_limit() {
+        let big = vec![0u8; 4096];
+        let data = tarball(&[("foo-0.1.0/Cargo.toml", MANIFEST), ("foo-0.1.0/big", &big)]);
+
+        let limits = Limits {
+            max_unpacked_size: 4096,
+            ..LIMITS
+        };
+
+        assert!(verify(&api::CrateMeta::for_test("foo", "0.1.0"), &data, limits).is_err());
+    }
+}
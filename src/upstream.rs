@@ -0,0 +1,355 @@
+//! Pull-through caching of crates from an upstream sparse registry, usually
+//! crates.io.
+//!
+//! Index files and `.crate` files of crates lagret doesn't know itself are
+//! fetched upstream and kept in the store, so they stay available when the
+//! upstream can't be reached.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use semver::Version;
+use tokio::sync::OnceCell;
+
+use crate::{
+    Error, Result,
+    error::Optional,
+    index,
+    store::{ByteRange, ObjectStream, Store},
+};
+
+static UPSTREAM_DIR: &str = "upstream";
+
+/// How long an unchanged cached index file goes without being rewritten, so
+/// that a store shared with other replicas or wiped by hand gets refilled.
+const INDEX_REFRESH: Duration = Duration::from_secs(60 * 60);
+
+fn cached_index_path(crate_name: &str) -> String {
+    format!("{UPSTREAM_DIR}/index/{}", index::index_path(crate_name))
+}
+
+fn cached_crate_path(crate_name: &str, version: &Version) -> String {
+    format!("{UPSTREAM_DIR}/crates/{crate_name}/{crate_name}-{version}.crate")
+}
+
+#[derive(serde::Deserialize)]
+struct UpstreamConfig {
+    dl: String,
+}
+
+/// The parts of an index line needed to verify downloads.
+#[derive(serde::Deserialize)]
+pub struct UpstreamVersion {
+    pub vers: Version,
+    pub cksum: String,
+}
+
+pub struct Upstream {
+    client: reqwest::Client,
+
+    /// The sparse index root, without the `sparse+` prefix.
+    index_url: String,
+
+    /// The `dl` template from the upstream `config.json`.
+    dl: OnceCell<String>,
+
+    /// Digests of the index files last written to the cache, and when.
+    cached_indexes: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl Upstream {
+    pub fn new(index_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("lagret/", env!("CARGO_PKG_VERSION")))
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(60))
+            .build()
+            .expect("building HTTP client");
+
+        Self {
+            client,
+            index_url: index_url
+                .trim_start_matches("sparse+")
+                .trim_end_matches('/')
+                .to_string(),
+            dl: OnceCell::new(),
+            cached_indexes: Mutex::default(),
+        }
+    }
+
+    async fn get(&self, url: &str) -> Result<Bytes> {
+        let res = self.client.get(url).send().await?;
+
+        match res.status().as_u16() {
+            403 | 404 | 410 | 451 => Err(Error::NotFound),
+            _ => Ok(res.error_for_status()?.bytes().await?),
+        }
+    }
+
+    /// Fetches the index file of a crate, bypassing the cache.
+    pub async fn fetch_index_file(&self, crate_name: &str) -> Result<Bytes> {
+        self.get(&format!(
+            "{}/{}",
+            self.index_url,
+            index::index_path(crate_name)
+        ))
+        .await
+    }
+
+    /// Fetches the index file of a crate, or serves the cached copy when the
+    /// upstream can't be reached.
+    pub async fn index_file(&self, store: &dyn Store, crate_name: &str) -> Result<Bytes> {
+        let cached = cached_index_path(crate_name);
+
+        match self.fetch_index_file(crate_name).await {
+            Ok(data) => {
+                let digest = sha256::digest(data.as_ref());
+
+                if self.needs_caching(crate_name, &digest) {
+                    match store.put_object(&cached, data.clone()).await {
+                        Ok(()) => {
+                            self.cached_indexes
+                                .lock()
+                                .expect("index cache poisoned")
+                                .insert(crate_name.to_string(), (digest, Instant::now()));
+                        }
+
+                        Err(err) => eprintln!("caching index of `{crate_name}`: {err}"),
+                    }
+                }
+
+                Ok(data)
+            }
+
+            Err(Error::Upstream(err)) => {
+                eprintln!("fetching index of `{crate_name}`, using cached copy: {err}");
+                store.get_object(&cached).await
+            }
+
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether a fetched index file differs from the cached copy, or the copy
+    /// wasn't refreshed for a while.
+    fn needs_caching(&self, crate_name: &str, digest: &str) -> bool {
+        match self
+            .cached_indexes
+            .lock()
+            .expect("index cache poisoned")
+            .get(crate_name)
+        {
+            Some((cached, written)) => cached != digest || written.elapsed() > INDEX_REFRESH,
+            None => true,
+        }
+    }
+
+    async fn dl_template(&self) -> Result<&str> {
+        self.dl
+            .get_or_try_init(|| async {
+                let data = self.get(&format!("{}/config.json", self.index_url)).await?;
+
+                Ok::<_, Error>(serde_json::from_slice::<UpstreamConfig>(&data)?.dl)
+            })
+            .await
+            .map(String::as_str)
+    }
+
+    /// Looks up the checksum of a version, preferring the cached index file so
+    /// that downloads don't need a second request.
+    pub async fn cksum(
+        &self,
+        store: &dyn Store,
+        crate_name: &str,
+        version: &Version,
+    ) -> Result<String> {
+        let find = |data: &[u8]| {
+            data.split(|b| *b == b'\n')
+                .filter_map(|line| serde_json::from_slice::<UpstreamVersion>(line).ok())
+                .find(|v| v.vers == *version)
+                .map(|v| v.cksum)
+        };
+
+        if let Some(data) = store
+            .get_object(&cached_index_path(crate_name))
+            .await
+            .optional()?
+            && let Some(cksum) = find(&data)
+        {
+            return Ok(cksum);
+        }
+
+        find(&self.index_file(store, crate_name).await?).ok_or(Error::NotFound)
+    }
+
+    /// Streams a `.crate` file from the cache, fetching and verifying it on
+    /// first use.
+    pub async fn download(
+        &self,
+        store: &dyn Store,
+        crate_name: &str,
+        version: &Version,
+        range: Option<ByteRange>,
+    ) -> Result<ObjectStream> {
+        let cached = cached_crate_path(crate_name, version);
+
+        match store.get_object_stream(&cached, range).await {
+            Err(Error::NotFound) => (),
+            res => return res,
+        }
+
+        let cksum = self.cksum(store, crate_name, version).await?;
+        let data = self.fetch_crate(crate_name, version, &cksum).await?;
+
+        store.put_object(&cached, data).await?;
+        store.get_object_stream(&cached, range).await
+    }
+
+    /// Fetches a `.crate` file, bypassing the cache, and checks it against
+    /// the checksum from the index.
+    pub async fn fetch_crate(
+        &self,
+        crate_name: &str,
+        version: &Version,
+        cksum: &str,
+    ) -> Result<Bytes> {
+        let url = render_dl(self.dl_template().await?, crate_name, version, cksum);
+        let data = self.get(&url).await?;
+
+        if sha256::digest(data.as_ref()) != cksum {
+            return Err(Error::Upstream(format!(
+                "checksum mismatch for `{crate_name}-{version}` from `{url}`"
+            )));
+        }
+
+        Ok(data)
+    }
+}
+
+/// Expands a `dl` template the way cargo does.
+fn render_dl(template: &str, crate_name: &str, version: &Version, cksum: &str) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+
+    if !MARKERS.iter().any(|marker| template.contains(marker)) {
+        return format!("{template}/{crate_name}/{version}/download");
+    }
+
+    let prefix = index::index_prefix(crate_name);
+
+    template
+        .replace("{crate}", crate_name)
+        .replace("{version}", &version.to_string())
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_ascii_lowercase())
+        .replace("{sha256-checksum}", cksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::local::LocalStorage;
+
+    use axum::{Router, routing};
+    use futures_util::StreamExt;
+
+    const CRATE: &[u8] = b"crate data";
+
+    async fn read(stream: Result<ObjectStream>) -> Vec<u8> {
+        let stream = stream.expect("downloading");
+
+        stream
+            .body
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await
+    }
+
+    /// Serves a tiny sparse registry with `foo 0.1.0`, standing in for
+    /// crates.io.
+    async fn mock_upstream() -> (String, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let config = format!(r#"{{"dl":"{url}/dl/{{crate}}-{{version}}"}}"#);
+        let index_line = format!(
+            r#"{{"name":"foo","vers":"0.1.0","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+            sha256::digest(CRATE)
+        );
+
+        let app = Router::new()
+            .route("/config.json", routing::get(move || async { config }))
+            .route("/3/f/foo", routing::get(move || async { index_line }))
+            .route("/dl/foo-0.1.0", routing::get(|| async { CRATE }));
+
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn caches_upstream_crates() {
+        let dir = tempfile::tempdir().expect("creating temp dir");
+        let store = LocalStorage::new(dir.path());
+
+        let (url, server) = mock_upstream().await;
+        let upstream = Upstream::new(&format!("sparse+{url}/"));
+        let version = "0.1.0".parse().unwrap();
+
+        assert!(upstream.index_file(&store, "foo").await.is_ok());
+        assert_eq!(
+            read(upstream.download(&store, "foo", &version, None).await).await,
+            CRATE
+        );
+        assert!(matches!(
+            upstream.index_file(&store, "bar").await,
+            Err(Error::NotFound)
+        ));
+
+        // Everything fetched once keeps working without the upstream.
+        server.abort();
+        let _ = server.await;
+
+        let offline = Upstream::new(&url);
+        assert!(offline.index_file(&store, "foo").await.is_ok());
+        assert_eq!(
+            read(
+                offline
+                    .download(&store, "foo", &version, Some(ByteRange::From(6)))
+                    .await
+            )
+            .await,
+            b"data"
+        );
+    }
+
+    #[test]
+    fn dl_templates() {
+        let version = "1.0.0".parse().unwrap();
+
+        assert_eq!(
+            render_dl("https://static.crates.io/crates", "Serde", &version, "ab"),
+            "https://static.crates.io/crates/Serde/1.0.0/download"
+        );
+        assert_eq!(
+            render_dl(
+                "https://x/{lowerprefix}/{crate}-{version}?c={sha256-checksum}",
+                "Serde",
+                &version,
+                "ab"
+            ),
+            "https://x/se/rd/Serde-1.0.0?c=ab"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Checks of publish payloads, following the rules crates.io applies.
+
+use semver::Version;
+
+use crate::{Error, Result, Settings, api};
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_KEYWORDS: usize = 5;
+const MAX_KEYWORD_LENGTH: usize = 20;
+
+/// Names that cannot be used as crate names, either because they clash with
+/// the standard library or are reserved file names on Windows.
+const RESERVED_NAMES: &[&str] = &[
+    "alloc",
+    "core",
+    "proc-macro",
+    "proc_macro",
+    "std",
+    "test",
+    "con",
+    "prn",
+    "aux",
+    "nul",
+    "com1",
+    "com2",
+    "com3",
+    "com4",
+    "com5",
+    "com6",
+    "com7",
+    "com8",
+    "com9",
+    "lpt1",
+    "lpt2",
+    "lpt3",
+    "lpt4",
+    "lpt5",
+    "lpt6",
+    "lpt7",
+    "lpt8",
+    "lpt9",
+];
+
+/// The index URLs of crates.io, which dependencies may refer to unless
+/// `LAGRET_ALLOWED_REGISTRIES` says otherwise.
+pub const CRATES_IO_INDEX: &[&str] = &[
+    "https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+fn validate_ident(what: &str, name: &str) -> Result<()> {
+    let Some(first) = name.chars().next() else {
+        return Err(Error::BadRequest(format!("{what} cannot be empty")));
+    };
+
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(Error::BadRequest(format!(
+            "{what} `{name}` is longer than {MAX_NAME_LENGTH} characters"
+        )));
+    }
+
+    if !first.is_ascii_alphabetic() {
+        return Err(Error::BadRequest(format!(
+            "{what} `{name}` must start with an ASCII letter"
+        )));
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-' && *c != '_')
+    {
+        return Err(Error::BadRequest(format!(
+            "invalid character `{c}` in {what} `{name}`, only ASCII letters, digits, `-` and `_` are allowed"
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn validate_crate_name(name: &str) -> Result<()> {
+    validate_ident("crate name", name)?;
+
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    {
+        return Err(Error::BadRequest(format!(
+            "crate name `{name}` is reserved"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Versions like `1.0.0+a` and `1.0.0+b` are distinct semver versions, but
+/// cargo treats them as the same.
+pub fn differs_only_in_build(a: &Version, b: &Version) -> bool {
+    a != b && (a.major, a.minor, a.patch, &a.pre) == (b.major, b.minor, b.patch, &b.pre)
+}
+
+/// A metadata field, or `None` when it is empty or blank. Cargo sends empty
+/// values as they are, but they count as missing.
+pub fn non_blank(value: Option<&str>) -> Option<&str> {
+    value.filter(|s| !s.trim().is_empty())
+}
+
+/// Validates everything in the metadata that does not depend on the index.
+/// Problems cargo can live with end up in the returned warnings.
+pub fn validate_meta(meta: &api::CrateMeta, settings: &Settings) -> Result<api::PublishWarnings> {
+    validate_crate_name(&meta.name)?;
+
+    if !settings.allow_build_metadata && !meta.vers.build.is_empty() {
+        return Err(Error::BadRequest(format!(
+            "version `{}` contains build metadata, which is not allowed",
+            meta.vers
+        )));
+    }
+
+    for dep in &meta.deps {
+        validate_ident("dependency name", &dep.name)?;
+
+        if let Some(name) = &dep.explicit_name_in_toml {
+            validate_ident("dependency name", name)?;
+        }
+
+        if let Some(registry) = &dep.registry
+            && !settings
+                .allowed_registries
+                .iter()
+                .any(|allowed| allowed == registry)
+        {
+            return Err(Error::BadRequest(format!(
+                "dependency `{}` is from registry `{registry}`, which is not allowed",
+                dep.name
+            )));
+        }
+    }
+
+    let mut warnings = api::PublishWarnings::default();
+
+    if meta.description.as_deref().is_none_or(str::is_empty) {
+        warnings.other.push("missing `description`".into());
+    }
+
+    if non_blank(meta.license.as_deref()).is_none()
+        && non_blank(meta.license_file.as_deref()).is_none()
+    {
+        warnings
+            .other
+            .push("missing `license` or `license-file`".into());
+    }
+
+    if meta.keywords.len() > MAX_KEYWORDS {
+        warnings.other.push(format!(
+            "only the first {MAX_KEYWORDS} keywords are used for search"
+        ));
+    }
+
+    for keyword in &meta.keywords {
+        if keyword.len() > MAX_KEYWORD_LENGTH
+            || !keyword
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '+')
+        {
+            warnings.other.push(format!("invalid keyword `{keyword}`"));
+        }
+    }
+
+    // lagret does not render badges, just like crates.io
+    warnings.invalid_badges.extend(meta.badges.keys().cloned());
+
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_names() {
+        assert!(validate_crate_name("serde_json").is_ok());
+        assert!(validate_crate_name("a").is_ok());
+        assert!(validate_crate_name("").is_err());
+        assert!(validate_crate_name("1password").is_err());
+        assert!(validate_crate_name("ünicode").is_err());
+        assert!(validate_crate_name("foo.bar").is_err());
+        assert!(validate_crate_name("Std").is_err());
+        assert!(validate_crate_name("nul").is_err());
+        assert!(validate_crate_name(&"a".repeat(65)).is_err());
+    }
+}
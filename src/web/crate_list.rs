@@ -0,0 +1,125 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{extract, response::Html};
+
+use crate::{IndexState, Settings, auth::ReadAuth, downloads::Downloads, search::Sort};
+
+use super::{Escape, page, query_encode};
+
+const PER_PAGE: usize = 50;
+
+/// The sort orders offered, with their query value and label.
+const SORTS: [(Sort, &str, &str); 4] = [
+    (Sort::Relevance, "relevance", "Relevance"),
+    (Sort::Alpha, "alpha", "Name"),
+    (Sort::Downloads, "downloads", "Downloads"),
+    (Sort::RecentUpdates, "recent-updates", "Recently updated"),
+];
+
+#[derive(serde::Deserialize)]
+pub struct Query {
+    #[serde(default)]
+    q: String,
+
+    #[serde(default)]
+    page: Option<usize>,
+
+    #[serde(default)]
+    sort: Option<Sort>,
+}
+
+/// Lists all crates by name, or the results of a search.
+pub async fn crate_list(
+    _: ReadAuth,
+    extract::Query(query): extract::Query<Query>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Html<String> {
+    let page_num = query.page.unwrap_or(1).max(1);
+
+    // Without a query every crate matches equally, so relevance means nothing.
+    let sort = query.sort.unwrap_or(if query.q.is_empty() {
+        Sort::Alpha
+    } else {
+        Sort::Relevance
+    });
+    let sort_name = SORTS
+        .iter()
+        .find(|(option, ..)| *option == sort)
+        .map_or("", |(_, value, _)| value);
+
+    let totals = downloads.totals().await;
+    let res = mtx
+        .read()
+        .await
+        .search_crates(&query.q, sort, &totals, page_num, PER_PAGE);
+    let total = res.meta.total;
+
+    let prefix = &settings.path_prefix;
+    let mut body = String::new();
+
+    if query.q.is_empty() {
+        let _ = write!(body, "<h1>Crates</h1><p class=\"muted\">{total} crates</p>");
+    } else {
+        let _ = write!(
+            body,
+            "<h1>Search results for “{}”</h1><p class=\"muted\">{total} crates</p>",
+            Escape(&query.q)
+        );
+    }
+
+    let link = |sort: &str, num: usize, label: &str| {
+        format!(
+            r#"<a href="{}/ui?q={}&amp;sort={sort}&amp;page={num}">{label}</a> "#,
+            Escape(prefix),
+            Escape(&query_encode(&query.q)),
+        )
+    };
+
+    body.push_str("<p>Sort by: ");
+
+    for (option, value, label) in SORTS {
+        if option == sort {
+            let _ = write!(body, "<b>{label}</b> ");
+        } else {
+            body.push_str(&link(value, 1, label));
+        }
+    }
+
+    body.push_str("</p><table>");
+
+    for item in &res.crates {
+        let _ = write!(
+            body,
+            r#"<tr><td><a href="{prefix}/ui/crates/{name}">{name}</a></td><td>{version}</td><td>{description}</td><td class="muted">{downloads}</td></tr>"#,
+            prefix = Escape(prefix),
+            name = Escape(&item.name),
+            version = Escape(&item.max_version.to_string()),
+            description = Escape(&item.description),
+            downloads = item.downloads,
+        );
+    }
+
+    body.push_str("</table><p>");
+
+    let page_link = |num: usize, label: &str| link(sort_name, num, label);
+
+    if page_num > 1 {
+        body.push_str(&page_link(page_num - 1, "← Previous"));
+    }
+
+    if page_num.saturating_mul(PER_PAGE) < total {
+        body.push_str(&page_link(page_num + 1, "Next →"));
+    }
+
+    body.push_str("</p>");
+
+    let title = if query.q.is_empty() {
+        "Crates"
+    } else {
+        "Search"
+    };
+
+    Html(page(&settings, title, &query.q, &body))
+}
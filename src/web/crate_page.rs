@@ -0,0 +1,243 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{extract, response::Html};
+
+use crate::{
+    Error, IndexEntry, IndexState, Result, Settings, StoreState,
+    api::{self, CrateDepKind},
+    auth::ReadAuth,
+    downloads::Downloads,
+    error::Optional,
+    index, readme,
+    store::Store,
+};
+
+use super::{Escape, page, safe_url, snippet};
+
+#[derive(serde::Deserialize)]
+pub struct CrateArgs {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct VersionArgs {
+    name: String,
+    version: api::Version,
+}
+
+/// Shows the newest version that isn't yanked.
+pub async fn crate_page(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<CrateArgs>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Result<Html<String>> {
+    let versions = mtx
+        .read()
+        .await
+        .clone_versions_ignore_case(&args.name)
+        .ok_or(Error::NotFound)?;
+
+    let shown = versions
+        .iter()
+        .rev()
+        .find(|entry| !entry.yanked)
+        .or(versions.last())
+        .ok_or(Error::NotFound)?
+        .meta
+        .vers
+        .clone();
+
+    render(store.as_ref(), &downloads, &settings, versions, &shown).await
+}
+
+pub async fn crate_version_page(
+    _: ReadAuth,
+    extract::Path(args): extract::Path<VersionArgs>,
+    extract::Extension(IndexState(mtx)): extract::Extension<IndexState>,
+    extract::Extension(StoreState(store)): extract::Extension<StoreState>,
+    extract::Extension(downloads): extract::Extension<Arc<Downloads>>,
+    extract::Extension(settings): extract::Extension<Arc<Settings>>,
+) -> Result<Html<String>> {
+    let versions = mtx
+        .read()
+        .await
+        .clone_versions_ignore_case(&args.name)
+        .ok_or(Error::NotFound)?;
+
+    if !versions.iter().any(|entry| entry.meta.vers == args.version) {
+        return Err(Error::NotFound);
+    }
+
+    render(
+        store.as_ref(),
+        &downloads,
+        &settings,
+        versions,
+        &args.version,
+    )
+    .await
+}
+
+async fn render(
+    store: &dyn Store,
+    downloads: &Downloads,
+    settings: &Settings,
+    versions: Vec<IndexEntry>,
+    shown: &api::Version,
+) -> Result<Html<String>> {
+    let entry = versions
+        .iter()
+        .find(|entry| entry.meta.vers == *shown)
+        .ok_or(Error::NotFound)?;
+    let meta = &entry.meta;
+
+    let counts = downloads.versions(&meta.name).await;
+    let prefix = Escape(&settings.path_prefix);
+
+    let mut body = String::new();
+
+    let _ = write!(
+        body,
+        "<h1>{} <span class=\"muted\">{}</span></h1>",
+        Escape(&meta.name),
+        Escape(&meta.vers.to_string())
+    );
+
+    if entry.yanked {
+        body.push_str("<p class=\"yanked\">This version has been yanked.</p>");
+    }
+
+    if let Some(description) = &meta.description {
+        let _ = write!(body, "<p>{}</p>", Escape(description));
+    }
+
+    body.push_str("<p>");
+
+    for tag in meta.keywords.iter().chain(&meta.categories) {
+        let _ = write!(body, "<span class=\"tag\">{}</span>", Escape(tag));
+    }
+
+    body.push_str("</p><ul>");
+
+    if let Some(license) = &meta.license {
+        let _ = write!(body, "<li>License: {}</li>", Escape(license));
+    }
+
+    if let Some(rust_version) = &meta.rust_version {
+        let _ = write!(body, "<li>Rust version: {}</li>", Escape(rust_version));
+    }
+
+    for (label, url) in [
+        ("Repository", &meta.repository),
+        ("Homepage", &meta.homepage),
+        ("Documentation", &meta.documentation),
+    ] {
+        if let Some(url) = url.as_deref().and_then(safe_url) {
+            let url = Escape(url);
+            let _ = write!(body, "<li>{label}: <a href=\"{url}\">{url}</a></li>");
+        }
+    }
+
+    let _ = write!(
+        body,
+        "<li>Downloads: {} of this version, {} in total</li></ul>",
+        counts.get(&meta.vers).copied().unwrap_or_default(),
+        counts.values().sum::<u64>()
+    );
+
+    body.push_str("<h2>Install</h2><p>Add to <code>Cargo.toml</code>:</p>");
+    body.push_str(&snippet(&format!(
+        "{} = {{ version = \"{}\", registry = \"{}\" }}",
+        meta.name, meta.vers, settings.registry_name
+    )));
+
+    body.push_str("<p>with the registry in <code>.cargo/config.toml</code>:</p>");
+    body.push_str(&snippet(&format!(
+        "[registries.{}]\nindex = \"sparse+{}/\"",
+        settings.registry_name, settings.public_url
+    )));
+
+    if !meta.features.is_empty() {
+        body.push_str("<h2>Features</h2><table>");
+
+        for (feature, enables) in &meta.features {
+            let _ = write!(
+                body,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                Escape(feature),
+                Escape(&enables.join(", "))
+            );
+        }
+
+        body.push_str("</table>");
+    }
+
+    if !meta.deps.is_empty() {
+        body.push_str("<h2>Dependencies</h2><table>");
+
+        for dep in &meta.deps {
+            let kind = match dep.kind {
+                CrateDepKind::Normal => "",
+                CrateDepKind::Dev => "dev",
+                CrateDepKind::Build => "build",
+            };
+            let optional = if dep.optional { "optional" } else { "" };
+
+            // Dependencies from other registries can't be linked to.
+            let name = if dep.registry.is_none() {
+                format!(
+                    "<a href=\"{prefix}/ui/crates/{name}\">{name}</a>",
+                    name = Escape(&dep.name)
+                )
+            } else {
+                Escape(&dep.name).to_string()
+            };
+
+            let _ = write!(
+                body,
+                "<tr><td>{name}</td><td>{}</td><td>{kind}</td><td>{optional}</td><td>{}</td></tr>",
+                Escape(&dep.version_req.to_string()),
+                Escape(dep.target.as_deref().unwrap_or_default()),
+            );
+        }
+
+        body.push_str("</table>");
+    }
+
+    body.push_str("<h2>Versions</h2><table>");
+
+    for entry in versions.iter().rev() {
+        let vers = entry.meta.vers.to_string();
+
+        let _ = write!(
+            body,
+            "<tr><td><a href=\"{prefix}/ui/crates/{name}/{vers}\">{vers}</a></td><td>{yanked}</td><td>{updated}</td><td>{downloads} downloads</td></tr>",
+            name = Escape(&entry.meta.name),
+            vers = Escape(&vers),
+            yanked = if entry.yanked {
+                "<span class=\"yanked\">yanked</span>"
+            } else {
+                ""
+            },
+            updated = entry.updated_at.map(index::rfc3339).unwrap_or_default(),
+            downloads = counts.get(&entry.meta.vers).copied().unwrap_or_default(),
+        );
+    }
+
+    body.push_str("</table>");
+
+    if let Some(readme_file) = &meta.readme_file
+        && let Some(readme) = store.load_readme(&meta.name, &meta.vers).await.optional()?
+    {
+        let _ = write!(
+            body,
+            "<div class=\"readme\">{}</div>",
+            readme::render_html(&readme, readme_file)
+        );
+    }
+
+    Ok(Html(page(settings, &meta.name, "", &body)))
+}
@@ -0,0 +1,143 @@
+//! A small server-rendered HTML frontend for browsing the registry.
+//!
+//! Pages are plain strings built from the in-memory index, every value from
+//! crate metadata goes through [`Escape`].
+
+mod crate_list;
+mod crate_page;
+
+use std::fmt::{self, Display, Write};
+
+use crate::Settings;
+
+pub use {crate_list::crate_list, crate_page::crate_page, crate_page::crate_version_page};
+
+/// Escapes text for use in HTML element content and quoted attributes.
+pub struct Escape<'a>(pub &'a str);
+
+impl Display for Escape<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                '\'' => f.write_str("&#39;")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Only links to web pages, anything else like `javascript:` is dropped.
+fn safe_url(url: &str) -> Option<&str> {
+    (url.starts_with("https://") || url.starts_with("http://")).then_some(url)
+}
+
+/// Percent-encodes a query string value.
+fn query_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 0; color: #222; }
+header { background: #2b3a42; padding: 0.8em 2em; display: flex; gap: 2em; align-items: center; }
+header a { color: #fff; font-weight: bold; text-decoration: none; font-size: 1.2em; }
+header input[type=search] { padding: 0.4em; width: 20em; }
+main { max-width: 60em; margin: 2em auto; padding: 0 1em; }
+table { border-collapse: collapse; width: 100%; }
+td, th { text-align: left; padding: 0.3em 0.6em; border-bottom: 1px solid #ddd; }
+pre { background: #f4f4f4; padding: 0.8em; overflow-x: auto; }
+.muted { color: #777; }
+.yanked { color: #b00; }
+.tag { background: #e8eef1; border-radius: 0.3em; padding: 0.1em 0.4em; margin-right: 0.3em; }
+.snippet { position: relative; }
+.snippet button { position: absolute; top: 0.4em; right: 0.4em; }
+.readme { border-top: 1px solid #ddd; margin-top: 2em; }
+"#;
+
+const SCRIPT: &str = r#"
+for (const button of document.querySelectorAll(".snippet button")) {
+    button.addEventListener("click", () => {
+        navigator.clipboard.writeText(button.parentElement.querySelector("pre").innerText);
+        button.innerText = "Copied";
+    });
+}
+"#;
+
+/// Wraps `body` in the common page layout.
+fn page(settings: &Settings, title: &str, query: &str, body: &str) -> String {
+    let prefix = Escape(&settings.path_prefix);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - lagret</title>
+<style>{STYLE}</style>
+</head>
+<body>
+<header>
+<a href="{prefix}/ui">lagret</a>
+<form action="{prefix}/ui" method="get"><input type="search" name="q" value="{query}" placeholder="Search crates"></form>
+</header>
+<main>
+{body}
+</main>
+<script>{SCRIPT}</script>
+</body>
+</html>
+"#,
+        title = Escape(title),
+        query = Escape(query),
+    )
+}
+
+/// A `<pre>` with a button copying its text.
+fn snippet(text: &str) -> String {
+    format!(
+        r#"<div class="snippet"><pre>{}</pre><button type="button">Copy</button></div>"#,
+        Escape(text)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            Escape(r#"<a href="x">Tom & 'Jerry'</a>"#).to_string(),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(Escape("plain ünïcode").to_string(), "plain ünïcode");
+    }
+
+    #[test]
+    fn safe_urls() {
+        assert_eq!(safe_url("https://example.com"), Some("https://example.com"));
+        assert_eq!(safe_url("http://example.com"), Some("http://example.com"));
+        assert_eq!(safe_url("javascript:alert(1)"), None);
+        assert_eq!(safe_url("//example.com"), None);
+    }
+
+    #[test]
+    fn query_encoding() {
+        assert_eq!(query_encode("serde_json-1.0~x"), "serde_json-1.0~x");
+        assert_eq!(query_encode("a b&c=d/é"), "a%20b%26c%3Dd%2F%C3%A9");
+    }
+}